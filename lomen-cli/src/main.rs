@@ -1,7 +1,6 @@
 use argh::FromArgs;
-use libloading::os::windows;
-use libloading::os::windows::Library;
-use windows::LOAD_WITH_ALTERED_SEARCH_PATH;
+use libloading::Library;
+use std::ffi::{CStr, CString, c_char};
 
 #[derive(FromArgs)]
 #[argh(description = "Tool to control keyboard lighting of HP OMEN laptops.
@@ -51,12 +50,107 @@ struct Args {
     )]
     all: Option<String>,
 
+    #[argh(
+        option,
+        description = "set color for all zones of the light bar (on models having one)"
+    )]
+    light_bar: Option<String>,
+
     #[argh(
         switch,
         short = 's',
         description = "change color smoothly instead of instantly"
     )]
     smooth: bool,
+
+    #[argh(
+        option,
+        description = "easing curve of smooth color change: linear, ease-in, ease-out, \
+        ease-in-out, cubic-bezier(x1,y1,x2,y2) or steps(n). Implies --smooth"
+    )]
+    easing: Option<String>,
+
+    #[argh(
+        option,
+        description = "color space of smooth color change: srgb, linear-rgb, oklab or hsl. \
+        Implies --smooth"
+    )]
+    color_space: Option<String>,
+
+    #[argh(
+        option,
+        short = 'b',
+        description = "set keyboard backlight brightness in percent (0-100)"
+    )]
+    brightness: Option<u8>,
+
+    #[argh(
+        option,
+        from_str_fn(parse_on_off),
+        description = "turn keyboard backlight on or off"
+    )]
+    lighting: Option<bool>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Effect(EffectCommand),
+    Play(PlayCommand),
+}
+
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "effect",
+    description = "play lighting effect on the keyboard: breathing, cycle, rainbow, wave, strobe, \
+    candle or gradient"
+)]
+struct EffectCommand {
+    #[argh(positional, description = "effect name")]
+    name: String,
+
+    #[argh(
+        option,
+        short = 'c',
+        description = "effect color, repeat it for effects using several colors (e.g. wave \
+        color and background or gradient stops)"
+    )]
+    color: Vec<String>,
+
+    #[argh(
+        option,
+        default = "0.5",
+        description = "number of effect cycles per second (default 0.5)"
+    )]
+    speed: f32,
+
+    #[argh(
+        switch,
+        description = "move the effect from the game zone towards the right one"
+    )]
+    reverse: bool,
+
+    #[argh(
+        option,
+        short = 'd',
+        description = "play the effect for given number of seconds instead of until interrupted"
+    )]
+    duration: Option<f32>,
+}
+
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "play",
+    description = "play keyframe animation file (.lomenanim in TOML or JSON format)"
+)]
+struct PlayCommand {
+    #[argh(positional, description = "animation file")]
+    file: String,
 }
 
 #[repr(C)]
@@ -68,11 +162,50 @@ pub struct ColorsData {
     pub game: u64,
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct StatusData {
+    pub enabled: bool,
+    pub brightness: u8,
+    pub level: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct CapabilitiesData {
+    pub lighting_supported: bool,
+    pub light_bar_supported: bool,
+    pub brightness_supported: bool,
+    pub keyboard_type: u8,
+    pub zone_count: u8,
+    pub per_key_rgb: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct TransitionData {
+    pub duration: u64,
+    pub fps: u8,
+    pub easing: *const c_char,
+    pub color_space: *const c_char,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct EffectData {
+    pub name: *const c_char,
+    pub colors: *const u64,
+    pub color_count: usize,
+    pub speed: f32,
+    pub reverse: bool,
+    pub duration: u64,
+    pub fps: u8,
+}
+
 const NO_COLOR: u64 = 0xFFFFFFFF;
 
 fn main() {
-    let lib = unsafe { Library::load_with_flags("lomen.dll", LOAD_WITH_ALTERED_SEARCH_PATH) }
-        .expect("Failed to load lomen.dll library");
+    let lib = load_library();
 
     /* no args */
     if std::env::args().count() <= 1 {
@@ -81,6 +214,14 @@ fn main() {
     }
 
     let args: Args = argh::from_env();
+    let smooth = args.smooth || args.easing.is_some() || args.color_space.is_some();
+    let easing = args.easing.as_deref().map(|easing| {
+        CString::new(easing).unwrap_or_else(|_| panic!("Invalid easing: {}", easing))
+    });
+    let color_space = args.color_space.as_deref().map(|color_space| {
+        CString::new(color_space).unwrap_or_else(|_| panic!("Invalid color space: {}", color_space))
+    });
+    let transition = transition_data(easing.as_deref(), color_space.as_deref());
 
     if args.right.is_some()
         || args.center.is_some()
@@ -94,68 +235,259 @@ fn main() {
             left: str_to_color(&args.left, &args.all),
             game: str_to_color(&args.game, &args.all),
         };
-        if args.smooth {
-            set_colors_smooth(&lib, data)
+        if smooth {
+            set_colors_smooth(&lib, data, &transition)
         } else {
             set_colors(&lib, data)
         }
     }
 
+    if args.light_bar.is_some() {
+        let color = str_to_color(&args.light_bar, &None);
+        let data = ColorsData {
+            right: color,
+            center: color,
+            left: color,
+            game: color,
+        };
+        if smooth {
+            set_light_bar_colors_smooth(&lib, data, &transition)
+        } else {
+            set_light_bar_colors(&lib, data)
+        }
+    }
+
+    if let Some(brightness) = args.brightness {
+        set_brightness(&lib, brightness);
+    }
+
+    if let Some(enabled) = args.lighting {
+        set_lighting_enabled(&lib, enabled);
+    }
+
     /* after all to show modified status */
     if args.info {
         print_info(&lib);
     }
+
+    /* blocks until the effect or animation ends */
+    match &args.command {
+        Some(Command::Effect(effect)) => play_effect(&lib, effect),
+        Some(Command::Play(play)) => play_animation_file(&lib, &play.file),
+        None => {}
+    }
+}
+
+#[cfg(windows)]
+fn load_library() -> Library {
+    use libloading::os::windows;
+
+    unsafe {
+        windows::Library::load_with_flags("lomen.dll", windows::LOAD_WITH_ALTERED_SEARCH_PATH)
+    }
+    .expect("Failed to load lomen.dll library")
+    .into()
+}
+
+#[cfg(not(windows))]
+fn load_library() -> Library {
+    let path = std::env::current_exe()
+        .expect("Failed to locate executable")
+        .with_file_name(libloading::library_filename("lomen"));
+
+    unsafe { Library::new(&path) }
+        .unwrap_or_else(|e| panic!("Failed to load {} library: {}", path.display(), e))
 }
 
 fn print_info(lib: &Library) {
-    println!("Keyboard type: {}", get_keyboard_type(lib));
-    println!("Lighting supported: {}", is_lighting_supported(lib));
+    let capabilities = get_capabilities(lib);
+    println!(
+        "Keyboard type: {}",
+        get_keyboard_type_name(lib, capabilities.keyboard_type)
+    );
+    println!("Keyboard zones: {}", capabilities.zone_count);
+    println!("Per-key RGB: {}", capabilities.per_key_rgb);
+    println!("Lighting supported: {}", capabilities.lighting_supported);
+    println!("Light bar supported: {}", capabilities.light_bar_supported);
+    println!(
+        "Brightness control supported: {}",
+        capabilities.brightness_supported
+    );
+
+    if capabilities.brightness_supported {
+        let status = get_status(lib);
+        println!("Lighting: {}", if status.enabled { "on" } else { "off" });
+        println!("Brightness: {}%", status.brightness);
+    }
+
+    if capabilities.zone_count > 0 {
+        print_colors("Zone colors", &get_colors(lib));
+    }
+
+    if capabilities.light_bar_supported {
+        print_colors("Light bar colors", &get_light_bar_colors(lib));
+    }
+}
 
-    let colors = get_colors(lib);
-    println!("Zone colors:");
+fn print_colors(title: &str, colors: &ColorsData) {
+    println!("{}:", title);
     println!("\t{:08}{:#08X}", "right", colors.right);
     println!("\t{:08}{:#08X}", "center", colors.center);
     println!("\t{:08}{:#08X}", "left", colors.left);
     println!("\t{:08}{:#08X}", "game", colors.game);
 }
 
-fn is_lighting_supported(lib: &Library) -> bool {
-    type Fn = extern "stdcall" fn() -> bool;
-    let fun = unsafe { lib.get::<Fn>(b"is_lighting_supported\0") }.unwrap();
-    fun()
+fn get_capabilities(lib: &Library) -> CapabilitiesData {
+    type Fn = extern "system" fn(*mut CapabilitiesData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"get_capabilities\0") }.unwrap();
+
+    let mut capabilities = CapabilitiesData::default();
+    check_status(lib, fun(&mut capabilities));
+    capabilities
 }
 
-fn get_keyboard_type(lib: &Library) -> u8 {
-    type Fn = extern "stdcall" fn() -> u8;
-    let fun = unsafe { lib.get::<Fn>(b"get_keyboard_type\0") }.unwrap();
-    fun()
+fn get_keyboard_type_name(lib: &Library, keyboard_type: u8) -> String {
+    type Fn = extern "system" fn(u8, *mut u8, usize) -> usize;
+    let fun = unsafe { lib.get::<Fn>(b"get_keyboard_type_name\0") }.unwrap();
+
+    let mut buffer = [0u8; 64];
+    let len = fun(keyboard_type, buffer.as_mut_ptr(), buffer.len()).min(buffer.len() - 1);
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
 }
 
 fn get_colors(lib: &Library) -> ColorsData {
-    type Fn = extern "stdcall" fn(*mut ColorsData);
+    type Fn = extern "system" fn(*mut ColorsData) -> i32;
     let fun = unsafe { lib.get::<Fn>(b"get_colors\0") }.unwrap();
 
     let mut colors = ColorsData::default();
-    fun(&mut colors);
+    check_status(lib, fun(&mut colors));
     colors
 }
 
 fn set_colors(lib: &Library, colors: ColorsData) {
-    type Fn = extern "stdcall" fn(*const ColorsData);
+    type Fn = extern "system" fn(*const ColorsData) -> i32;
     let fun = unsafe { lib.get::<Fn>(b"set_colors\0") }.unwrap();
-    fun(&colors);
+    check_status(lib, fun(&colors));
+}
+
+fn transition_data(easing: Option<&CStr>, color_space: Option<&CStr>) -> TransitionData {
+    TransitionData {
+        duration: 1000,
+        fps: 50,
+        easing: easing.map_or(std::ptr::null(), CStr::as_ptr),
+        color_space: color_space.map_or(std::ptr::null(), CStr::as_ptr),
+    }
+}
+
+fn set_colors_smooth(lib: &Library, colors: ColorsData, transition: &TransitionData) {
+    type Fn = extern "system" fn(*const ColorsData, *const TransitionData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"transit_colors_with\0") }.unwrap();
+    check_status(lib, fun(&colors, transition));
 }
 
-fn set_colors_smooth(lib: &Library, colors: ColorsData) {
-    type Fn = extern "stdcall" fn(*const ColorsData, u64, u8);
-    let fun = unsafe { lib.get::<Fn>(b"transit_colors\0") }.unwrap();
-    fun(&colors, 1000, 50);
+fn get_light_bar_colors(lib: &Library) -> ColorsData {
+    type Fn = extern "system" fn(*mut ColorsData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"get_light_bar_colors\0") }.unwrap();
+
+    let mut colors = ColorsData::default();
+    check_status(lib, fun(&mut colors));
+    colors
+}
+
+fn set_light_bar_colors(lib: &Library, colors: ColorsData) {
+    type Fn = extern "system" fn(*const ColorsData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"set_light_bar_colors\0") }.unwrap();
+    check_status(lib, fun(&colors));
+}
+
+fn set_light_bar_colors_smooth(lib: &Library, colors: ColorsData, transition: &TransitionData) {
+    type Fn = extern "system" fn(*const ColorsData, *const TransitionData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"transit_light_bar_colors_with\0") }.unwrap();
+    check_status(lib, fun(&colors, transition));
+}
+
+fn play_effect(lib: &Library, effect: &EffectCommand) {
+    type StartFn = extern "system" fn(*const EffectData) -> i32;
+    type WaitFn = extern "system" fn() -> i32;
+    let start = unsafe { lib.get::<StartFn>(b"start_effect\0") }.unwrap();
+    let wait = unsafe { lib.get::<WaitFn>(b"wait_animation\0") }.unwrap();
+
+    let name = CString::new(effect.name.as_str())
+        .unwrap_or_else(|_| panic!("Invalid effect: {}", effect.name));
+    let colors: Vec<u64> = effect
+        .color
+        .iter()
+        .map(|color| str_to_color(&Some(color.clone()), &None))
+        .collect();
+    let data = EffectData {
+        name: name.as_ptr(),
+        colors: colors.as_ptr(),
+        color_count: colors.len(),
+        speed: effect.speed,
+        reverse: effect.reverse,
+        duration: effect
+            .duration
+            .map_or(0, |seconds| (seconds * 1000.0).max(1.0) as u64),
+        fps: 50,
+    };
+
+    check_status(lib, start(&data));
+    check_status(lib, wait());
+}
+
+fn play_animation_file(lib: &Library, file: &str) {
+    type StartFn = extern "system" fn(*const c_char) -> i32;
+    type WaitFn = extern "system" fn() -> i32;
+    let start = unsafe { lib.get::<StartFn>(b"start_animation_file\0") }.unwrap();
+    let wait = unsafe { lib.get::<WaitFn>(b"wait_animation\0") }.unwrap();
+
+    let path = CString::new(file).unwrap_or_else(|_| panic!("Invalid file name: {}", file));
+    check_status(lib, start(path.as_ptr()));
+    check_status(lib, wait());
+}
+
+fn get_status(lib: &Library) -> StatusData {
+    type Fn = extern "system" fn(*mut StatusData) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"get_status\0") }.unwrap();
+
+    let mut status = StatusData::default();
+    check_status(lib, fun(&mut status));
+    status
+}
+
+fn set_brightness(lib: &Library, brightness: u8) {
+    type Fn = extern "system" fn(u8) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"set_brightness\0") }.unwrap();
+    check_status(lib, fun(brightness));
+}
+
+fn set_lighting_enabled(lib: &Library, enabled: bool) {
+    type Fn = extern "system" fn(bool) -> i32;
+    let fun = unsafe { lib.get::<Fn>(b"set_lighting_enabled\0") }.unwrap();
+    check_status(lib, fun(enabled));
+}
+
+fn get_last_error(lib: &Library) -> String {
+    type Fn = extern "system" fn(*mut u8, usize) -> usize;
+    let fun = unsafe { lib.get::<Fn>(b"get_last_error\0") }.unwrap();
+
+    let mut buffer = [0u8; 512];
+    let len = fun(buffer.as_mut_ptr(), buffer.len()).min(buffer.len() - 1);
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+/// Prints library error message and exits if the status is not successful
+fn check_status(lib: &Library, status: i32) {
+    if status != 0 {
+        eprintln!("Error: {}", get_last_error(lib));
+        std::process::exit(status);
+    }
 }
 
 fn str_to_color(color: &Option<String>, default_color: &Option<String>) -> u64 {
     let parse_hex_color = |s: &str| -> u64 {
         let x = s.strip_prefix('#').unwrap_or(s);
-        u64::from_str_radix(x, 16).expect(format!("Invalid color code: {}", s).as_str())
+        u64::from_str_radix(x, 16).unwrap_or_else(|_| panic!("Invalid color code: {}", s))
     };
 
     match color {
@@ -166,3 +498,11 @@ fn str_to_color(color: &Option<String>, default_color: &Option<String>) -> u64 {
         },
     }
 }
+
+fn parse_on_off(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected `on` or `off`, but `{}` given", value)),
+    }
+}
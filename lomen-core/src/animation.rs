@@ -0,0 +1,694 @@
+use crate::backend;
+use crate::backend::Backend;
+use crate::clock::{Clock, SystemClock};
+use crate::color::LightingColors;
+use crate::compositor::SharedCompositor;
+use crate::device::{OmenDevice, SharedSessionCache};
+use crate::effect::Effect;
+use crate::error::LomenError;
+use crate::scheduler::{FrameScheduler, FrameStats};
+use crate::transition::{LightingColorsTransition, TransitionOptions, progress};
+use std::any::Any;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// Opens backend for a worker thread. Backends are not `Send` (WMI connection is bound to the
+/// thread that created it), so every worker opens its own one.
+pub type BackendFactory = dyn Fn() -> Result<Box<dyn Backend>, LomenError> + Send + Sync;
+
+/// Creates clock timing frames of a worker thread
+pub type ClockFactory = dyn Fn() -> Box<dyn Clock> + Send + Sync;
+
+/// Colors buffer driven by transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Zones,
+    LightBar,
+}
+
+/// Transition played by animation, kept to continue from it when the animation is superseded
+#[derive(Clone, Copy)]
+struct Motion {
+    target: Target,
+    transition: LightingColorsTransition,
+    duration: Duration,
+}
+
+/// State shared by animation worker and its handles
+#[derive(Default)]
+struct AnimationState {
+    cancelled: AtomicBool,
+    /// Bits of `f32` progress
+    progress: AtomicU32,
+    motion: Mutex<Option<Motion>>,
+    result: Mutex<Option<Result<FrameStats, LomenError>>>,
+    finished: Condvar,
+}
+
+impl AnimationState {
+    fn finish(&self, result: Result<FrameStats, LomenError>) {
+        if result.is_ok() {
+            self.progress.store(1.0f32.to_bits(), Ordering::Relaxed);
+        }
+        *self.result.lock().unwrap() = Some(result);
+        self.finished.notify_all();
+    }
+}
+
+/// Cancellation, progress reporting and clock available to animation running on a worker
+pub struct AnimationControl {
+    state: Arc<AnimationState>,
+    clock: Box<dyn Clock>,
+}
+
+impl AnimationControl {
+    /// Returns clock the animation should take frame times from
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`LomenError::Cancelled`] once the animation has been cancelled
+    pub fn check_cancelled(&self) -> Result<(), LomenError> {
+        if self.is_cancelled() {
+            return Err(LomenError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Reports progress in range `0.0..=1.0`
+    pub fn set_progress(&self, progress: f32) {
+        self.state
+            .progress
+            .store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Handle of animation running on a background worker.
+///
+/// Dropping the handle does not stop the animation, call [`AnimationHandle::cancel`] for that.
+#[derive(Clone)]
+pub struct AnimationHandle {
+    state: Arc<AnimationState>,
+}
+
+impl AnimationHandle {
+    /// Asks the animation to stop before its next frame
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the animation finishes. Cancelled animation fails with
+    /// [`LomenError::Cancelled`].
+    pub fn wait(&self) -> Result<FrameStats, LomenError> {
+        let mut result = self.state.result.lock().unwrap();
+        loop {
+            if let Some(result) = result.as_ref() {
+                return result.clone();
+            }
+            result = self.state.finished.wait(result).unwrap();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.result.lock().unwrap().is_none()
+    }
+
+    /// Returns progress of the animation in range `0.0..=1.0`
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.state.progress.load(Ordering::Relaxed))
+    }
+}
+
+/// Runs animations on background workers, one at a time.
+///
+/// Starting an animation supersedes the running one: it is cancelled and waited for, so frames
+/// of the two never interleave.
+pub struct Animator {
+    factory: Arc<BackendFactory>,
+    clock: Arc<ClockFactory>,
+    cache: Option<SharedSessionCache>,
+    current: Option<AnimationHandle>,
+}
+
+impl Animator {
+    pub fn new(
+        factory: impl Fn() -> Result<Box<dyn Backend>, LomenError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            factory: Arc::new(factory),
+            clock: Arc::new(|| Box::new(SystemClock::new())),
+            cache: None,
+            current: None,
+        }
+    }
+
+    /// Makes worker sessions share colors buffers cache with other sessions, so that they see
+    /// colors written by animations
+    pub fn with_session_cache(mut self, cache: SharedSessionCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Makes workers take frame times from clocks created by `clock`
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> Box<dyn Clock> + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Creates animator opening backend native for the current platform
+    pub fn native() -> Self {
+        Self::new(backend::default_backend)
+    }
+
+    /// Returns handle of the last started animation
+    pub fn current(&self) -> Option<&AnimationHandle> {
+        self.current.as_ref()
+    }
+
+    /// Cancels the running animation and continues its transition of `target` buffer towards
+    /// `to_colors` from the colors it has shown last.
+    fn supersede(
+        &mut self,
+        target: Target,
+        to_colors: LightingColors,
+        options: &TransitionOptions,
+    ) -> Option<LightingColorsTransition> {
+        let handle = self.current.take()?;
+        let running = handle.is_running();
+        handle.cancel();
+        let _ = handle.wait();
+
+        let motion = (*handle.state.motion.lock().unwrap())?;
+        if !running || motion.target != target {
+            return None;
+        }
+
+        Some(
+            motion
+                .transition
+                .retarget(handle.progress(), motion.duration, to_colors, options),
+        )
+    }
+
+    /// Cancels the running animation and waits until it stops
+    pub fn cancel(&mut self) {
+        if let Some(handle) = self.current.take() {
+            handle.cancel();
+            let _ = handle.wait();
+        }
+    }
+
+    /// Runs `animation` on a worker thread with its own device session
+    pub fn spawn(
+        &mut self,
+        animation: impl FnOnce(&mut OmenDevice, &AnimationControl) -> Result<FrameStats, LomenError>
+        + Send
+        + 'static,
+    ) -> AnimationHandle {
+        self.cancel();
+
+        let state = Arc::new(AnimationState::default());
+        let worker_state = state.clone();
+        let factory = self.factory.clone();
+        let clock = self.clock.clone();
+        let cache = self.cache.clone();
+
+        thread::spawn(move || {
+            let control = AnimationControl {
+                state: worker_state,
+                clock: clock(),
+            };
+            // Panicking animation must still finish, otherwise its handles wait forever
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                factory().and_then(|backend| {
+                    let mut device = match cache {
+                        Some(cache) => OmenDevice::with_shared_cache(backend, cache),
+                        None => OmenDevice::with_backend(backend),
+                    };
+                    animation(&mut device, &control)
+                })
+            }))
+            .unwrap_or_else(|payload| Err(LomenError::Backend(panic_message(payload.as_ref()))));
+            control.state.finish(result);
+        });
+
+        let handle = AnimationHandle { state };
+        self.current = Some(handle.clone());
+        handle
+    }
+
+    /// Smoothly changes keyboard lighting colors in background. A running transition of
+    /// keyboard colors is retargeted: the new one starts from the colors shown last keeping
+    /// their velocity.
+    pub fn transit_colors(
+        &mut self,
+        to_colors: LightingColors,
+        options: TransitionOptions,
+    ) -> AnimationHandle {
+        self.transit(Target::Zones, to_colors, options)
+    }
+
+    /// Smoothly changes light bar colors in background retargeting running transition of light
+    /// bar colors
+    pub fn transit_light_bar_colors(
+        &mut self,
+        to_colors: LightingColors,
+        options: TransitionOptions,
+    ) -> AnimationHandle {
+        self.transit(Target::LightBar, to_colors, options)
+    }
+
+    /// Plays effect on keyboard zones in background for given duration or until cancelled
+    pub fn play_effect(
+        &mut self,
+        mut effect: Box<dyn Effect>,
+        duration: Option<Duration>,
+        fps: u8,
+    ) -> AnimationHandle {
+        self.spawn(move |device, control| {
+            let scheduler = match duration {
+                Some(duration) => FrameScheduler::new(duration, fps)?,
+                None => FrameScheduler::endless(fps)?,
+            };
+
+            scheduler.run_timed_with_clock(control.clock(), |t| {
+                control.check_cancelled()?;
+                device.set_colors(&effect.frame(t))?;
+                if let Some(duration) = duration {
+                    control.set_progress(progress(t, duration));
+                }
+                Ok(())
+            })
+        })
+    }
+
+    /// Writes composited layers to keyboard zones in background until cancelled. Layers can be
+    /// changed while it runs; when played again, the compositor continues from the last frame.
+    pub fn play_compositor(&mut self, compositor: SharedCompositor, fps: u8) -> AnimationHandle {
+        self.cancel();
+        compositor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .resume();
+        self.play_effect(Box::new(compositor), None, fps)
+    }
+
+    fn transit(
+        &mut self,
+        target: Target,
+        to_colors: LightingColors,
+        options: TransitionOptions,
+    ) -> AnimationHandle {
+        let retargeted = self.supersede(target, to_colors, &options);
+
+        self.spawn(move |device, control| {
+            let scheduler = FrameScheduler::new(options.duration, options.fps)?;
+            let transition = match retargeted {
+                Some(transition) => transition,
+                None => {
+                    let from_colors = match target {
+                        Target::Zones => device.get_colors()?,
+                        Target::LightBar => device.get_light_bar_colors()?,
+                    };
+                    LightingColorsTransition::new(from_colors, to_colors).with_options(&options)
+                }
+            };
+            *control.state.motion.lock().unwrap() = Some(Motion {
+                target,
+                transition,
+                duration: options.duration,
+            });
+
+            scheduler.run_with_clock(control.clock(), |progress| {
+                control.check_cancelled()?;
+                let colors = transition.sample(progress);
+                match target {
+                    Target::Zones => device.set_colors(&colors)?,
+                    Target::LightBar => device.set_light_bar_colors(&colors)?,
+                }
+                control.set_progress(progress);
+                Ok(())
+            })
+        })
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown reason");
+    format!("animation panicked: {}", message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::EmulatorBackend;
+    use crate::clock::ManualClock;
+    use crate::color::Color;
+    use crate::compositor::{Layer, LayerSlot};
+    use crate::easing::Easing;
+    use crate::effect::{EffectKind, EffectParams};
+    use crate::protocol::{BiosRequest, BiosResponse, CMD_TYPE_SET_ZONE_COLORS};
+
+    /// Emulator shared between worker threads and the test. Red channel of the right zone is
+    /// recorded for every written frame.
+    #[derive(Clone, Default)]
+    struct SharedEmulator(Arc<Mutex<EmulatorBackend>>, Arc<Mutex<Vec<u8>>>);
+
+    impl Backend for SharedEmulator {
+        fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+            if request.command_type == CMD_TYPE_SET_ZONE_COLORS {
+                self.1.lock().unwrap().push(request.data[25]);
+            }
+            self.0.lock().unwrap().execute(request)
+        }
+    }
+
+    fn animator() -> (Animator, SharedEmulator) {
+        let emulator = SharedEmulator::default();
+        let shared = emulator.clone();
+        (
+            Animator::new(move || Ok(Box::new(shared.clone()))),
+            emulator,
+        )
+    }
+
+    /// Lets the test run worker frames one by one. Worker clock passes the gate before waiting
+    /// for the next frame; once the watched animation is cancelled, the gate lets it through.
+    #[derive(Default)]
+    struct Gate {
+        state: Mutex<GateState>,
+        changed: Condvar,
+    }
+
+    #[derive(Default)]
+    struct GateState {
+        permits: u32,
+        parked: bool,
+        watched: Option<AnimationHandle>,
+    }
+
+    impl Gate {
+        /// Lets worker render `frames` more frames
+        fn allow(&self, frames: u32) {
+            let mut state = self.state.lock().unwrap();
+            state.permits += frames;
+            state.parked = false;
+            self.changed.notify_all();
+        }
+
+        fn watch(&self, handle: &AnimationHandle) {
+            self.state.lock().unwrap().watched = Some(handle.clone());
+        }
+
+        /// Blocks until worker has rendered all allowed frames
+        fn wait_parked(&self) {
+            let mut state = self.state.lock().unwrap();
+            while !state.parked || state.permits > 0 {
+                state = self.changed.wait(state).unwrap();
+            }
+        }
+
+        fn pass(&self) {
+            let mut state = self.state.lock().unwrap();
+            loop {
+                if state.watched.as_ref().is_some_and(|h| h.is_cancelled()) {
+                    break;
+                }
+                if state.permits > 0 {
+                    state.permits -= 1;
+                    break;
+                }
+                state.parked = true;
+                self.changed.notify_all();
+                // Cancellation is not signalled through the condvar, so check it periodically
+                state = self
+                    .changed
+                    .wait_timeout(state, Duration::from_millis(1))
+                    .unwrap()
+                    .0;
+            }
+            state.parked = false;
+        }
+    }
+
+    struct GatedClock {
+        clock: ManualClock,
+        gate: Arc<Gate>,
+    }
+
+    impl Clock for GatedClock {
+        fn elapsed(&self) -> Duration {
+            self.clock.elapsed()
+        }
+
+        fn sleep_until(&self, deadline: Duration) {
+            self.gate.pass();
+            self.clock.sleep_until(deadline);
+        }
+    }
+
+    /// Animator with workers timed by manual clocks advancing only through the gate
+    fn gated_animator() -> (Animator, SharedEmulator, Arc<Gate>) {
+        let (animator, emulator) = animator();
+        let gate = Arc::new(Gate::default());
+        let shared = gate.clone();
+        let animator = animator.with_clock(move || {
+            Box::new(GatedClock {
+                clock: ManualClock::new(),
+                gate: shared.clone(),
+            })
+        });
+        (animator, emulator, gate)
+    }
+
+    fn colors(color: u64) -> LightingColors {
+        LightingColors {
+            right: Some(Color::from(color)),
+            center: Some(Color::from(color)),
+            left: Some(Color::from(color)),
+            game: Some(Color::from(color)),
+        }
+    }
+
+    fn read_colors(emulator: &SharedEmulator) -> LightingColors {
+        OmenDevice::with_backend(Box::new(emulator.clone()))
+            .get_colors()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_wait() {
+        let (mut animator, emulator) = animator();
+
+        let handle = animator.transit_colors(
+            colors(0x0080FF),
+            TransitionOptions::new(Duration::from_millis(50), 50),
+        );
+        let stats = handle.wait().unwrap();
+
+        assert!(stats.frames > 1);
+        assert!(!handle.is_running());
+        assert_eq!(1.0, handle.progress());
+        assert_eq!(colors(0x0080FF), read_colors(&emulator));
+    }
+
+    #[test]
+    fn test_does_not_block() {
+        let (mut animator, _) = animator();
+
+        let handle = animator.transit_colors(
+            colors(0xFFFFFF),
+            TransitionOptions::new(Duration::from_secs(10), 10),
+        );
+
+        assert!(handle.is_running());
+        assert!(handle.progress() < 1.0);
+        handle.cancel();
+        assert!(matches!(handle.wait(), Err(LomenError::Cancelled)));
+        assert!(!handle.is_running());
+    }
+
+    #[test]
+    fn test_supersede() {
+        let (mut animator, emulator) = animator();
+
+        let first = animator.transit_colors(
+            colors(0xFFFFFF),
+            TransitionOptions::new(Duration::from_secs(10), 10),
+        );
+        let second = animator.transit_colors(
+            colors(0x123456),
+            TransitionOptions::new(Duration::from_millis(20), 50),
+        );
+
+        // The first animation has stopped before the second one was started
+        assert!(!first.is_running());
+        assert!(matches!(first.wait(), Err(LomenError::Cancelled)));
+        assert!(second.wait().is_ok());
+        assert_eq!(colors(0x123456), read_colors(&emulator));
+    }
+
+    #[test]
+    fn test_retarget() {
+        let (mut animator, emulator, gate) = gated_animator();
+        let initial = read_colors(&emulator);
+        let period = Duration::from_millis(20);
+        let first_options = TransitionOptions::new(Duration::from_secs(2), 50);
+        let second_options =
+            TransitionOptions::new(Duration::from_millis(300), 50).with_easing(Easing::EaseInOut);
+
+        // The first transition is superseded once it has shown the frame at 300 ms
+        gate.allow(15);
+        let first = animator.transit_colors(colors(0xFF0000), first_options);
+        gate.watch(&first);
+        gate.wait_parked();
+        let second = animator.transit_colors(colors(0x000000), second_options);
+
+        assert!(matches!(first.wait(), Err(LomenError::Cancelled)));
+        assert!(second.wait().is_ok());
+        assert_eq!(colors(0x000000), read_colors(&emulator));
+
+        // Frames continue from the shown color keeping its velocity. Frames equal to the
+        // previous one are not written.
+        let transition =
+            LightingColorsTransition::new(initial, colors(0xFF0000)).with_options(&first_options);
+        let retargeted = transition.retarget(
+            progress(period * 15, first_options.duration),
+            first_options.duration,
+            colors(0x000000),
+            &second_options,
+        );
+        let red = |colors: LightingColors| colors.right.unwrap().r;
+        let mut expected = vec![red(initial)];
+        expected.extend(
+            (0..=15).map(|i| red(transition.sample(progress(period * i, first_options.duration)))),
+        );
+        expected.extend(
+            (0..15).map(|i| red(retargeted.sample(progress(period * i, second_options.duration)))),
+        );
+        expected.push(red(retargeted.sample(1.0)));
+        expected.dedup();
+        assert_eq!(expected[1..], emulator.1.lock().unwrap()[..]);
+    }
+
+    #[test]
+    fn test_play_effect() {
+        let (mut animator, emulator) = animator();
+        let effect = EffectKind::Strobe
+            .create(&EffectParams {
+                colors: vec![Color::from(0x00FF00)],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let handle = animator.play_effect(effect, Some(Duration::from_millis(100)), 50);
+
+        assert!(handle.wait().is_ok());
+        assert_eq!(colors(0x00FF00), read_colors(&emulator));
+    }
+
+    #[test]
+    fn test_cancel_endless_effect() {
+        let (mut animator, emulator, gate) = gated_animator();
+        let effect = EffectKind::Rainbow
+            .create(&EffectParams::default())
+            .unwrap();
+
+        gate.allow(2);
+        let handle = animator.play_effect(effect, None, 50);
+        gate.watch(&handle);
+        gate.wait_parked();
+        assert!(handle.is_running());
+        assert_eq!(0.0, handle.progress());
+
+        animator.cancel();
+        assert!(matches!(handle.wait(), Err(LomenError::Cancelled)));
+        assert_eq!(3, emulator.1.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_play_compositor() {
+        let (mut animator, emulator, gate) = gated_animator();
+        let compositor = SharedCompositor::default();
+        compositor
+            .lock()
+            .unwrap()
+            .set(LayerSlot::Base, Layer::colors(colors(0x0000FF)));
+
+        let handle = animator.play_compositor(compositor.clone(), 50);
+        gate.watch(&handle);
+        gate.wait_parked();
+        assert_eq!(colors(0x0000FF), read_colors(&emulator));
+
+        // Layers changed while playing are picked up by the next frame
+        compositor.lock().unwrap().set(
+            LayerSlot::Notification,
+            Layer::colors(colors(0xFF0000)).with_duration(Duration::from_millis(100)),
+        );
+        gate.allow(1);
+        gate.wait_parked();
+        assert_eq!(colors(0xFF0000), read_colors(&emulator));
+
+        // The notification added at 0 ms expires with the frame at 100 ms
+        gate.allow(3);
+        gate.wait_parked();
+        assert_eq!(colors(0xFF0000), read_colors(&emulator));
+        gate.allow(1);
+        gate.wait_parked();
+        assert_eq!(colors(0x0000FF), read_colors(&emulator));
+
+        animator.cancel();
+        assert!(matches!(handle.wait(), Err(LomenError::Cancelled)));
+        assert_eq!(vec![0x00, 0xFF, 0x00], *emulator.1.lock().unwrap());
+    }
+
+    #[test]
+    fn test_panic() {
+        let (mut animator, _) = animator();
+
+        let handle = animator.spawn(|_, _| panic!("broken effect"));
+
+        assert!(matches!(
+            handle.wait(),
+            Err(LomenError::Backend(message)) if message.contains("broken effect")
+        ));
+        // The next animation is not blocked by the panicked one
+        let next = animator.transit_colors(
+            colors(0x000000),
+            TransitionOptions::new(Duration::from_millis(20), 50),
+        );
+        assert!(next.wait().is_ok());
+    }
+
+    #[test]
+    fn test_backend_failure() {
+        let mut animator =
+            Animator::new(|| Err(LomenError::Connection("no connection".to_string())));
+
+        let handle = animator.transit_colors(
+            colors(0xFFFFFF),
+            TransitionOptions::new(Duration::from_millis(20), 50),
+        );
+
+        assert!(matches!(handle.wait(), Err(LomenError::Connection(_))));
+    }
+}
@@ -0,0 +1,41 @@
+use crate::error::LomenError;
+use crate::protocol::{BiosRequest, BiosResponse};
+
+mod emulator;
+mod sysfs;
+#[cfg(all(windows, feature = "wmi"))]
+mod wmi;
+
+pub use self::emulator::EmulatorBackend;
+pub use self::sysfs::{DEFAULT_LED_NAMES, DEFAULT_SYSFS_ROOT, SysfsBackend};
+#[cfg(all(windows, feature = "wmi"))]
+pub use self::wmi::WmiBackend;
+
+/// Transport executing HP BIOS lighting commands
+pub trait Backend {
+    /// Executes request and returns BIOS response.
+    ///
+    /// Errors are reserved for transport failures, BIOS rejections are reported through
+    /// response return code.
+    fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError>;
+}
+
+/// Returns backend native for the current platform
+#[cfg(all(windows, feature = "wmi"))]
+pub fn default_backend() -> Result<Box<dyn Backend>, LomenError> {
+    Ok(Box::new(WmiBackend::new()?))
+}
+
+/// Returns backend native for the current platform
+#[cfg(target_os = "linux")]
+pub fn default_backend() -> Result<Box<dyn Backend>, LomenError> {
+    Ok(Box::new(SysfsBackend::default()))
+}
+
+/// Returns backend native for the current platform
+#[cfg(not(any(all(windows, feature = "wmi"), target_os = "linux")))]
+pub fn default_backend() -> Result<Box<dyn Backend>, LomenError> {
+    Err(LomenError::Unsupported(
+        "no lighting backend available for this platform".to_string(),
+    ))
+}
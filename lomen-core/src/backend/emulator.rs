@@ -0,0 +1,284 @@
+use crate::backend::Backend;
+use crate::error::{LomenError, ReturnCode};
+use crate::protocol::{
+    BUFFER_SIZE, BiosRequest, BiosResponse, CMD_COMMON, CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE,
+    CMD_TYPE_GET_LIGHT_BAR_COLORS, CMD_TYPE_GET_PLATFORM_INFO, CMD_TYPE_GET_ZONE_COLORS,
+    CMD_TYPE_SET_BRIGHTNESS, CMD_TYPE_SET_LIGHT_BAR_COLORS, CMD_TYPE_SET_ZONE_COLORS,
+    CMD_TYPE_STATUS, LIGHTING_LEVEL_ON, PLATFORM_LIGHT_BAR_FLAG, SIGN,
+};
+use std::collections::HashMap;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Stateful in-memory emulation of OMEN BIOS lighting interface
+#[derive(Debug, Clone)]
+pub struct EmulatorBackend {
+    platform_info: u8,
+    keyboard_type: u8,
+    lighting_level: u8,
+    zone_buffer: [u8; BUFFER_SIZE],
+    light_bar_buffer: [u8; BUFFER_SIZE],
+    return_codes: HashMap<u32, u32>,
+    response_sizes: HashMap<u32, usize>,
+    delay: Duration,
+    history: Vec<(u32, u32)>,
+}
+
+impl Default for EmulatorBackend {
+    fn default() -> Self {
+        Self {
+            platform_info: 1,
+            keyboard_type: 0,
+            lighting_level: LIGHTING_LEVEL_ON,
+            zone_buffer: [0; BUFFER_SIZE],
+            light_bar_buffer: [0; BUFFER_SIZE],
+            return_codes: HashMap::new(),
+            response_sizes: HashMap::new(),
+            delay: Duration::ZERO,
+            history: Vec::new(),
+        }
+    }
+}
+
+impl EmulatorBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the first byte of platform info response (bit 0 means lighting support, bit 1 means
+    /// light bar presence)
+    pub fn set_platform_info(&mut self, value: u8) {
+        self.platform_info = value;
+    }
+
+    /// Sets keyboard type reported by the device
+    pub fn set_keyboard_type(&mut self, value: u8) {
+        self.keyboard_type = value;
+    }
+
+    /// Returns lighting level (on flag and brightness)
+    pub fn lighting_level(&self) -> u8 {
+        self.lighting_level
+    }
+
+    /// Sets lighting level (on flag and brightness)
+    pub fn set_lighting_level(&mut self, value: u8) {
+        self.lighting_level = value;
+    }
+
+    /// Returns emulated zone colors buffer
+    pub fn zone_buffer(&self) -> &[u8; BUFFER_SIZE] {
+        &self.zone_buffer
+    }
+
+    /// Replaces emulated zone colors buffer
+    pub fn set_zone_buffer(&mut self, buffer: [u8; BUFFER_SIZE]) {
+        self.zone_buffer = buffer;
+    }
+
+    /// Returns emulated light bar colors buffer
+    pub fn light_bar_buffer(&self) -> &[u8; BUFFER_SIZE] {
+        &self.light_bar_buffer
+    }
+
+    /// Replaces emulated light bar colors buffer
+    pub fn set_light_bar_buffer(&mut self, buffer: [u8; BUFFER_SIZE]) {
+        self.light_bar_buffer = buffer;
+    }
+
+    /// Makes commands of given type fail with given `rwReturnCode`. Zero code restores success.
+    pub fn set_return_code(&mut self, command_type: u32, code: u32) {
+        if code == 0 {
+            self.return_codes.remove(&command_type);
+        } else {
+            self.return_codes.insert(command_type, code);
+        }
+    }
+
+    /// Truncates (or pads with zeros) responses to commands of given type to `size` bytes
+    pub fn set_response_size(&mut self, command_type: u32, size: usize) {
+        self.response_sizes.insert(command_type, size);
+    }
+
+    /// Sets time every command takes to execute
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// Returns `(command, command_type)` pairs of all executed commands
+    pub fn history(&self) -> &[(u32, u32)] {
+        &self.history
+    }
+
+    /// Clears executed commands history
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    fn process(&mut self, request: &BiosRequest) -> Result<Vec<u8>, ReturnCode> {
+        if request.sign != SIGN {
+            return Err(ReturnCode::WrongSignature);
+        }
+
+        let mut out = vec![0u8; BUFFER_SIZE];
+        match (request.command, request.command_type) {
+            (CMD_COMMON, CMD_TYPE_GET_PLATFORM_INFO) => out[0] = self.platform_info,
+            (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS) => out.copy_from_slice(&self.zone_buffer),
+            (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS) => {
+                let size = request.data.len();
+                if size > BUFFER_SIZE {
+                    return Err(ReturnCode::InvalidParameters);
+                }
+                self.zone_buffer[..size].copy_from_slice(&request.data);
+            }
+            (CMD_COMMON, CMD_TYPE_GET_LIGHT_BAR_COLORS | CMD_TYPE_SET_LIGHT_BAR_COLORS)
+                if self.platform_info & PLATFORM_LIGHT_BAR_FLAG == 0 =>
+            {
+                return Err(ReturnCode::NotSupported);
+            }
+            (CMD_COMMON, CMD_TYPE_GET_LIGHT_BAR_COLORS) => {
+                out.copy_from_slice(&self.light_bar_buffer)
+            }
+            (CMD_COMMON, CMD_TYPE_SET_LIGHT_BAR_COLORS) => {
+                let size = request.data.len();
+                if size > BUFFER_SIZE {
+                    return Err(ReturnCode::InvalidParameters);
+                }
+                self.light_bar_buffer[..size].copy_from_slice(&request.data);
+            }
+            (CMD_COMMON, CMD_TYPE_STATUS) => out[0] = self.lighting_level,
+            (CMD_COMMON, CMD_TYPE_SET_BRIGHTNESS) => match request.data.first() {
+                Some(&level) => self.lighting_level = level,
+                None => return Err(ReturnCode::InvalidParameters),
+            },
+            (CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE) => out[0] = self.keyboard_type,
+            (CMD_COMMON | CMD_GAMING, _) => return Err(ReturnCode::UnknownCommandType),
+            _ => return Err(ReturnCode::UnknownCommand),
+        }
+
+        if let Some(&size) = self.response_sizes.get(&request.command_type) {
+            out.resize(size, 0);
+        }
+
+        Ok(out)
+    }
+}
+
+impl Backend for EmulatorBackend {
+    fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+        self.history.push((request.command, request.command_type));
+        sleep(self.delay);
+
+        if let Some(&code) = self.return_codes.get(&request.command_type) {
+            return Ok(BiosResponse::error(code.into()));
+        }
+
+        Ok(match self.process(request) {
+            Ok(data) => BiosResponse::ok(data),
+            Err(code) => BiosResponse::error(code),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn execute(backend: &mut EmulatorBackend, request: BiosRequest) -> Result<Vec<u8>, LomenError> {
+        backend.execute(&request).unwrap().into_data()
+    }
+
+    #[test]
+    fn test_set_then_get_zone_colors() {
+        let mut backend = EmulatorBackend::new();
+        let mut data = [0u8; BUFFER_SIZE];
+        data[25] = 0xAA;
+
+        execute(
+            &mut backend,
+            BiosRequest::with_data(CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS, &data),
+        )
+        .unwrap();
+        let result = execute(
+            &mut backend,
+            BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS),
+        )
+        .unwrap();
+
+        assert_eq!(data.to_vec(), result);
+        assert_eq!(
+            &[
+                (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS),
+                (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS)
+            ],
+            backend.history()
+        );
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut backend = EmulatorBackend::new();
+
+        assert!(matches!(
+            execute(&mut backend, BiosRequest::new(0, CMD_TYPE_GET_ZONE_COLORS)),
+            Err(LomenError::ReturnCode(ReturnCode::UnknownCommand))
+        ));
+        assert!(matches!(
+            execute(&mut backend, BiosRequest::new(CMD_COMMON, 99)),
+            Err(LomenError::ReturnCode(ReturnCode::UnknownCommandType))
+        ));
+    }
+
+    #[test]
+    fn test_wrong_signature() {
+        let mut backend = EmulatorBackend::new();
+        let mut request = BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS);
+        request.sign = *b"XXXX";
+
+        assert!(matches!(
+            execute(&mut backend, request),
+            Err(LomenError::ReturnCode(ReturnCode::WrongSignature))
+        ));
+    }
+
+    #[test]
+    fn test_return_code() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_return_code(CMD_TYPE_GET_PLATFORM_INFO, 5);
+
+        let request = BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_PLATFORM_INFO);
+        assert!(matches!(
+            execute(&mut backend, request.clone()),
+            Err(LomenError::ReturnCode(ReturnCode::InvalidParameters))
+        ));
+
+        backend.set_return_code(CMD_TYPE_GET_PLATFORM_INFO, 0);
+        assert!(execute(&mut backend, request).is_ok());
+    }
+
+    #[test]
+    fn test_response_size() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_response_size(CMD_TYPE_GET_ZONE_COLORS, 10);
+
+        let result = execute(
+            &mut backend,
+            BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS),
+        );
+        assert_eq!(10, result.unwrap().len());
+    }
+
+    #[test]
+    fn test_oversized_request() {
+        let mut backend = EmulatorBackend::new();
+        let data = [0u8; BUFFER_SIZE + 1];
+
+        assert!(matches!(
+            execute(
+                &mut backend,
+                BiosRequest::with_data(CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS, &data)
+            ),
+            Err(LomenError::ReturnCode(ReturnCode::InvalidParameters))
+        ));
+    }
+}
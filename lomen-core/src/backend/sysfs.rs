@@ -0,0 +1,342 @@
+use crate::backend::Backend;
+use crate::color::Color;
+use crate::error::LomenError;
+use crate::protocol::{
+    BUFFER_SIZE, BiosRequest, BiosResponse, CMD_COMMON, CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE,
+    CMD_TYPE_GET_PLATFORM_INFO, CMD_TYPE_GET_ZONE_COLORS, CMD_TYPE_SET_BRIGHTNESS,
+    CMD_TYPE_SET_ZONE_COLORS, CMD_TYPE_STATUS, LIGHTING_ON_FLAG, MAX_BRIGHTNESS,
+};
+use crate::response::ZoneColors;
+use log::debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Default location of LED class devices
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys/class/leds";
+
+/// Default names of multicolor LED devices of right, center, left and game zones
+pub const DEFAULT_LED_NAMES: [&str; 4] = [
+    "hp_omen::kbd_backlight-zone1",
+    "hp_omen::kbd_backlight-zone2",
+    "hp_omen::kbd_backlight-zone3",
+    "hp_omen::kbd_backlight-zone4",
+];
+
+/// Backend driving keyboard zones exposed by the kernel as multicolor LED class devices.
+///
+/// Every zone is a directory containing `multi_index`, `multi_intensity`, `brightness` and
+/// `max_brightness` attributes. Turning lighting off zeroes LED brightness, so the brightness
+/// to restore is kept by the backend.
+#[derive(Debug, Clone)]
+pub struct SysfsBackend {
+    root: PathBuf,
+    led_names: [String; 4],
+    brightness: u8,
+}
+
+impl Default for SysfsBackend {
+    fn default() -> Self {
+        Self::new(DEFAULT_SYSFS_ROOT)
+    }
+}
+
+impl SysfsBackend {
+    /// Creates backend looking up zone LEDs with default names in given directory
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            led_names: DEFAULT_LED_NAMES.map(String::from),
+            brightness: MAX_BRIGHTNESS,
+        }
+    }
+
+    /// Overrides names of right, center, left and game zone LED devices
+    pub fn with_led_names(mut self, names: [&str; 4]) -> Self {
+        self.led_names = names.map(String::from);
+        self
+    }
+
+    fn led_path(&self, zone_index: usize) -> PathBuf {
+        self.root.join(&self.led_names[zone_index])
+    }
+
+    fn is_lighting_supported(&self) -> bool {
+        (0..self.led_names.len()).all(|i| self.led_path(i).join("multi_intensity").is_file())
+    }
+
+    fn read_zone_color(&self, zone_index: usize) -> Result<Color, LomenError> {
+        let path = self.led_path(zone_index);
+        let max = read_max_brightness(&path)?;
+        let channels = read_channels(&path)?;
+        let intensities = read_attribute(&path, "multi_intensity")?
+            .split_whitespace()
+            .map(|s| s.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|values| values.len() == channels.len())
+            .ok_or_else(|| {
+                LomenError::InvalidResponse(format!("invalid intensities in: {}", path.display()))
+            })?;
+
+        let mut rgb = [0u8; 3];
+        for (channel, intensity) in channels.iter().zip(intensities) {
+            rgb[*channel] = (intensity.min(max) * 255 / max) as u8;
+        }
+
+        Ok(Color::new(rgb[0], rgb[1], rgb[2]))
+    }
+
+    /// Reads lighting level (on flag and brightness percent) from the first zone LED
+    fn read_lighting_level(&self) -> Result<u8, LomenError> {
+        let path = self.led_path(0);
+        let max = read_max_brightness(&path)?;
+        let value: u32 = read_attribute(&path, "brightness")?.parse().map_err(|_| {
+            LomenError::InvalidResponse(format!("invalid brightness in: {}", path.display()))
+        })?;
+
+        match value {
+            0 => Ok(self.brightness),
+            value => Ok(LIGHTING_ON_FLAG | (value.min(max) * MAX_BRIGHTNESS as u32 / max) as u8),
+        }
+    }
+
+    fn write_lighting_level(&mut self, level: u8) -> Result<(), LomenError> {
+        self.brightness = (level & !LIGHTING_ON_FLAG).min(MAX_BRIGHTNESS);
+        let enabled = level & LIGHTING_ON_FLAG != 0;
+
+        for i in 0..self.led_names.len() {
+            let path = self.led_path(i);
+            let max = read_max_brightness(&path)?;
+            let value = if enabled {
+                self.brightness as u32 * max / MAX_BRIGHTNESS as u32
+            } else {
+                0
+            };
+
+            debug!("Writing brightness `{}` to: {}", value, path.display());
+            fs::write(path.join("brightness"), value.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_zone_color(&self, zone_index: usize, color: Color) -> Result<(), LomenError> {
+        let path = self.led_path(zone_index);
+        let max = read_max_brightness(&path)?;
+        let rgb = [color.r, color.g, color.b];
+        let value = read_channels(&path)?
+            .iter()
+            .map(|&channel| (rgb[channel] as u32 * max / 255).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        debug!("Writing `{}` to: {}", value, path.display());
+        fs::write(path.join("multi_intensity"), value)?;
+
+        Ok(())
+    }
+}
+
+fn read_attribute(path: &Path, name: &str) -> Result<String, LomenError> {
+    let path = path.join(name);
+    let value = fs::read_to_string(&path).map_err(|e| {
+        io::Error::new(e.kind(), format!("error reading {}: {}", path.display(), e))
+    })?;
+
+    Ok(value.trim().to_string())
+}
+
+fn read_max_brightness(path: &Path) -> Result<u32, LomenError> {
+    match read_attribute(path, "max_brightness")?.parse() {
+        Ok(0) | Err(_) => Err(LomenError::InvalidResponse(format!(
+            "invalid max brightness in: {}",
+            path.display()
+        ))),
+        Ok(max) => Ok(max),
+    }
+}
+
+/// Returns RGB component indices in the order of `multi_intensity` values
+fn read_channels(path: &Path) -> Result<Vec<usize>, LomenError> {
+    read_attribute(path, "multi_index")?
+        .split_whitespace()
+        .map(|name| match name {
+            "red" => Ok(0),
+            "green" => Ok(1),
+            "blue" => Ok(2),
+            other => Err(LomenError::Unsupported(format!("LED channel: {}", other))),
+        })
+        .collect()
+}
+
+impl Backend for SysfsBackend {
+    fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+        debug!(
+            "Executing command: {:?}, type: {:?}",
+            request.command, request.command_type
+        );
+
+        let mut out = vec![0u8; BUFFER_SIZE];
+        match (request.command, request.command_type) {
+            (CMD_COMMON, CMD_TYPE_GET_PLATFORM_INFO) => {
+                out[0] = self.is_lighting_supported() as u8;
+            }
+            (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS) => {
+                let mut zone_colors = ZoneColors::default();
+                for i in 0..self.led_names.len() {
+                    zone_colors.set_zone_color(i, Some(self.read_zone_color(i)?));
+                }
+                out = zone_colors.as_bytes().to_vec();
+            }
+            (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS) => {
+                let zone_colors = ZoneColors::parse(request.data.clone())
+                    .map_err(|e| LomenError::InvalidArgument(e.to_string()))?;
+
+                for i in 0..self.led_names.len() {
+                    self.write_zone_color(i, zone_colors.zone_color(i))?;
+                }
+            }
+            (CMD_COMMON, CMD_TYPE_STATUS) => out[0] = self.read_lighting_level()?,
+            (CMD_COMMON, CMD_TYPE_SET_BRIGHTNESS) => match request.data.first() {
+                Some(&level) => self.write_lighting_level(level)?,
+                None => {
+                    return Err(LomenError::InvalidArgument(
+                        "lighting level expected".to_string(),
+                    ));
+                }
+            },
+            /* sysfs does not expose keyboard layout, report standard one */
+            (CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE) => {}
+            _ => {
+                return Err(LomenError::Unsupported(format!(
+                    "command: {}, type: {} by sysfs backend",
+                    request.command, request.command_type
+                )));
+            }
+        }
+
+        Ok(BiosResponse::ok(out))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::LightingColors;
+    use crate::control;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// Creates fake LED class directory tree and returns its root
+    fn create_leds_tree(multi_index: &str, max_brightness: u32) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "lomen-sysfs-{}-{}",
+            process::id(),
+            DIR_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        for name in DEFAULT_LED_NAMES {
+            let path = root.join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("multi_index"), format!("{}\n", multi_index)).unwrap();
+            fs::write(path.join("multi_intensity"), "0 0 0\n").unwrap();
+            fs::write(path.join("brightness"), format!("{}\n", max_brightness)).unwrap();
+            fs::write(path.join("max_brightness"), format!("{}\n", max_brightness)).unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn test_is_lighting_supported() {
+        let root = create_leds_tree("red green blue", 255);
+
+        assert!(control::is_lighting_supported(&mut SysfsBackend::new(&root)).unwrap());
+        assert!(
+            !control::is_lighting_supported(&mut SysfsBackend::new(root.join("missing"))).unwrap()
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_set_colors() {
+        let root = create_leds_tree("red green blue", 255);
+        let mut backend = SysfsBackend::new(&root);
+
+        control::set_colors(
+            &mut backend,
+            &LightingColors {
+                right: Some(Color::from(0xAA0000)),
+                center: None,
+                left: Some(Color::from(0x00BB00)),
+                game: Some(Color::from(0x112233)),
+            },
+        )
+        .unwrap();
+
+        let read = |name: &str| fs::read_to_string(root.join(name).join("multi_intensity"));
+        assert_eq!("170 0 0", read(DEFAULT_LED_NAMES[0]).unwrap());
+        assert_eq!("0 0 0", read(DEFAULT_LED_NAMES[1]).unwrap());
+        assert_eq!("0 187 0", read(DEFAULT_LED_NAMES[2]).unwrap());
+        assert_eq!("17 34 51", read(DEFAULT_LED_NAMES[3]).unwrap());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_get_colors_with_custom_channels() {
+        let root = create_leds_tree("blue green red", 100);
+        fs::write(
+            root.join(DEFAULT_LED_NAMES[0]).join("multi_intensity"),
+            "100 50 0",
+        )
+        .unwrap();
+
+        let colors = control::get_colors(&mut SysfsBackend::new(&root)).unwrap();
+        assert_eq!(Some(Color::new(0, 127, 255)), colors.right);
+        assert_eq!(Some(Color::new(0, 0, 0)), colors.center);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_brightness() {
+        let root = create_leds_tree("red green blue", 200);
+        let mut backend = SysfsBackend::new(&root);
+        let read = |name: &str| fs::read_to_string(root.join(name).join("brightness"));
+
+        assert_eq!(100, control::get_brightness(&mut backend).unwrap());
+
+        control::set_brightness(&mut backend, 25).unwrap();
+        assert_eq!("50", read(DEFAULT_LED_NAMES[0]).unwrap());
+        assert_eq!("50", read(DEFAULT_LED_NAMES[3]).unwrap());
+        assert_eq!(25, control::get_brightness(&mut backend).unwrap());
+
+        control::set_lighting_enabled(&mut backend, false).unwrap();
+        assert_eq!("0", read(DEFAULT_LED_NAMES[0]).unwrap());
+        assert_eq!(25, control::get_brightness(&mut backend).unwrap());
+
+        control::set_lighting_enabled(&mut backend, true).unwrap();
+        assert_eq!("50", read(DEFAULT_LED_NAMES[2]).unwrap());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_custom_led_names() {
+        let root = create_leds_tree("red green blue", 255);
+        let mut backend = SysfsBackend::new(&root).with_led_names(["a", "b", "c", "d"]);
+
+        assert!(!control::is_lighting_supported(&mut backend).unwrap());
+        assert!(matches!(
+            control::get_colors(&mut backend),
+            Err(LomenError::Io(_))
+        ));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use crate::backend::Backend;
+use crate::error::{LomenError, ReturnCode};
+use crate::protocol::{BiosRequest, BiosResponse};
+use log::debug;
+use wmi::{IWbemClassWrapper, Variant, WMIConnection, WMIError};
+
+/// Backend calling `hpqBIOSInt128` method of HP BIOS WMI interface.
+///
+/// Connection and class objects are obtained once and reused by every call.
+#[derive(Debug)]
+pub struct WmiBackend {
+    connection: WMIConnection,
+    data_in: IWbemClassWrapper,
+    method_params: IWbemClassWrapper,
+}
+
+impl WmiBackend {
+    /// Connects to BIOS WMI interface and looks up its classes
+    pub fn new() -> Result<Self, LomenError> {
+        let connection = WMIConnection::with_namespace_path(r"root\wmi")
+            .map_err(|e| LomenError::Connection(e.to_string()))?;
+
+        let data_in = connection
+            .get_object("hpqBDataIn")
+            .map_err(|_| LomenError::ClassNotFound("hpqBDataIn".to_string()))?;
+
+        let method_params = connection
+            .get_object("hpqBIntM")
+            .map_err(|_| LomenError::ClassNotFound("hpqBIntM".to_string()))?
+            .get_method("hpqBIOSInt128")?
+            .ok_or_else(|| LomenError::ClassNotFound("hpqBIntM.hpqBIOSInt128".to_string()))?;
+
+        Ok(Self {
+            connection,
+            data_in,
+            method_params,
+        })
+    }
+}
+
+fn bytes_to_variant(bytes: &[u8]) -> Variant {
+    Variant::Array(bytes.iter().copied().map(Variant::UI1).collect())
+}
+
+/* HRESULTs reported when the process is not elevated */
+const WBEM_E_ACCESS_DENIED: i32 = 0x80041003u32 as i32;
+const E_ACCESSDENIED: i32 = 0x80070005u32 as i32;
+
+impl From<WMIError> for LomenError {
+    fn from(value: WMIError) -> Self {
+        match value {
+            WMIError::HResultError {
+                hres: WBEM_E_ACCESS_DENIED | E_ACCESSDENIED,
+            } => LomenError::ReturnCode(ReturnCode::AccessDenied),
+            other => LomenError::Backend(other.to_string()),
+        }
+    }
+}
+
+fn variant_to_bytes(v: Variant) -> Result<Vec<u8>, LomenError> {
+    match v {
+        Variant::Array(vec) => {
+            let mut out = Vec::with_capacity(vec.len());
+            for (i, item) in vec.into_iter().enumerate() {
+                match item {
+                    Variant::UI1(b) => out.push(b),
+                    other => {
+                        return Err(LomenError::InvalidResponse(format!(
+                            "element {} has unsupported type: {:?}",
+                            i, other
+                        )));
+                    }
+                }
+            }
+            Ok(out)
+        }
+        other => Err(LomenError::InvalidResponse(format!(
+            "Variant::Array expected, but {:?} found",
+            other
+        ))),
+    }
+}
+
+impl Backend for WmiBackend {
+    fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+        debug!(
+            "Executing command: {:?}, type: {:?}",
+            request.command, request.command_type
+        );
+
+        /* rejects oversized data before touching WMI */
+        request.encode()?;
+
+        let payload = if request.data.is_empty() {
+            Variant::Null
+        } else {
+            bytes_to_variant(&request.data)
+        };
+
+        let in_data = &self.data_in;
+        in_data.put_property("Sign", bytes_to_variant(&request.sign))?;
+        in_data.put_property("Command", Variant::UI4(request.command))?;
+        in_data.put_property("CommandType", Variant::UI4(request.command_type))?;
+        in_data.put_property("Size", Variant::UI4(request.data.len() as u32))?;
+        in_data.put_property("hpqBData", payload)?;
+
+        let in_params = self.method_params.spawn_instance()?;
+        in_params.put_property("InData", in_data.clone())?;
+
+        let out_params = self
+            .connection
+            .exec_method(
+                r"hpqBIntM.InstanceName='ACPI\PNP0C14\0_0'",
+                "hpqBIOSInt128",
+                Some(&in_params),
+            )?
+            .ok_or_else(|| LomenError::InvalidResponse("no output parameters".to_string()))?;
+
+        let out_data: IWbemClassWrapper = out_params.get_property("OutData")?.try_into()?;
+
+        let return_code: u32 = out_data.get_property("rwReturnCode")?.try_into()?;
+        let data = if return_code == 0 {
+            variant_to_bytes(out_data.get_property("Data")?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(BiosResponse {
+            sign: request.sign,
+            return_code,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::control;
+
+    #[test]
+    #[ignore = "requires OMEN hardware"]
+    fn test_is_lighting_supported() {
+        let result = control::is_lighting_supported(&mut WmiBackend::new().unwrap());
+        assert!(result.is_ok());
+
+        println!("Lighting supported: {}", result.unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires OMEN hardware"]
+    fn test_get_keyboard_type() {
+        let result = control::get_keyboard_type(&mut WmiBackend::new().unwrap());
+        assert!(result.is_ok());
+
+        println!("Keyboard type: {}", result.unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires OMEN hardware"]
+    fn test_get_colors() {
+        let result = control::get_colors(&mut WmiBackend::new().unwrap());
+        assert!(result.is_ok());
+
+        println!("Colors: {:?}", result);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::keyboard::KeyboardType;
+use crate::response::PlatformInfo;
+
+/// Lighting features of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether keyboard lighting is supported
+    pub lighting_supported: bool,
+    /// Whether the device has a light bar
+    pub light_bar_supported: bool,
+    /// Whether backlight brightness and on/off state can be controlled
+    pub brightness_supported: bool,
+    pub keyboard_type: KeyboardType,
+    /// Number of keyboard zones driven by zone colors commands
+    pub zone_count: u8,
+    /// Whether every key is lit independently
+    pub per_key_rgb: bool,
+}
+
+impl Capabilities {
+    pub fn new(
+        platform_info: &PlatformInfo,
+        keyboard_type: KeyboardType,
+        brightness_supported: bool,
+    ) -> Self {
+        let keyboard = keyboard_type.capabilities();
+        let zone_count = if platform_info.lighting_supported {
+            keyboard.zone_count
+        } else {
+            0
+        };
+
+        Self {
+            lighting_supported: platform_info.lighting_supported,
+            light_bar_supported: platform_info.light_bar_supported,
+            brightness_supported,
+            keyboard_type,
+            zone_count,
+            per_key_rgb: keyboard.per_key_rgb,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_no_zones_without_lighting() {
+        let platform_info = PlatformInfo::parse(vec![0b10]).unwrap();
+        let capabilities = Capabilities::new(&platform_info, KeyboardType::Standard, false);
+
+        assert!(!capabilities.lighting_supported);
+        assert!(capabilities.light_bar_supported);
+        assert_eq!(0, capabilities.zone_count);
+    }
+}
@@ -0,0 +1,97 @@
+use std::cell::Cell;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Source of time driving transitions and frame scheduling
+pub trait Clock {
+    /// Returns time elapsed since the clock was started
+    fn elapsed(&self) -> Duration;
+
+    /// Blocks until [`Clock::elapsed`] reaches `deadline`
+    fn sleep_until(&self, deadline: Duration);
+}
+
+/// Wall clock started on creation
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        sleep(deadline.saturating_sub(self.elapsed()));
+    }
+}
+
+/// Clock advanced only explicitly. Sleeping moves it forward instantly.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    elapsed: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, elapsed: Duration) {
+        self.elapsed.set(elapsed);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        if deadline > self.elapsed.get() {
+            self.elapsed.set(deadline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        clock.advance(Duration::from_millis(10));
+        clock.sleep_until(Duration::from_millis(5));
+        assert_eq!(Duration::from_millis(10), clock.elapsed());
+
+        clock.sleep_until(Duration::from_millis(30));
+        assert_eq!(Duration::from_millis(30), clock.elapsed());
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let clock = SystemClock::new();
+        clock.sleep_until(Duration::from_millis(5));
+        assert!(clock.elapsed() >= Duration::from_millis(5));
+    }
+}
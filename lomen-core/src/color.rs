@@ -11,14 +11,14 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
 }
 
-impl Into<u64> for Color {
-    fn into(self) -> u64 {
-        ((self.r as u64) << 16) | ((self.g as u64) << 8) | (self.b as u64)
+impl From<Color> for u64 {
+    fn from(color: Color) -> Self {
+        ((color.r as u64) << 16) | ((color.g as u64) << 8) | (color.b as u64)
     }
 }
 
@@ -89,17 +89,15 @@ impl Display for LightingColors {
 //     }
 // }
 
-impl Into<Vec<String>> for LightingColors {
-    fn into(self) -> Vec<String> {
-        let format = |c: Option<Color>| -> String {
-            c.map(|c| c.to_string()).unwrap_or_else(|| "".to_string())
-        };
+impl From<LightingColors> for Vec<String> {
+    fn from(colors: LightingColors) -> Self {
+        let format = |c: Option<Color>| -> String { c.map(|c| c.to_string()).unwrap_or_default() };
 
         vec![
-            format(self.right),
-            format(self.center),
-            format(self.left),
-            format(self.game),
+            format(colors.right),
+            format(colors.center),
+            format(colors.left),
+            format(colors.game),
         ]
     }
 }
@@ -110,7 +108,10 @@ impl From<Vec<String>> for LightingColors {
             if s.is_empty() {
                 None
             } else {
-                Some(Color::from_str(&s).expect(&format!("Error parsing color from: `{s}`")))
+                Some(
+                    Color::from_str(s)
+                        .unwrap_or_else(|_| panic!("Error parsing color from: `{s}`")),
+                )
             }
         };
 
@@ -0,0 +1,326 @@
+use crate::color::Color;
+use crate::error::LomenError;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Color space in which transitions interpolate colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Plain interpolation of gamma-encoded sRGB channels
+    #[default]
+    Srgb,
+    /// Interpolation of linear-light RGB channels, keeps mixed colors from getting too dark
+    LinearRgb,
+    /// Perceptually uniform OKLab space
+    Oklab,
+    /// HSL space going the shortest way around the hue circle
+    Hsl,
+}
+
+impl ColorSpace {
+    /// Returns color between `from` and `to` at `factor` in range `0.0..=1.0`
+    pub fn interpolate(&self, from: Color, to: Color, factor: f32) -> Color {
+        self.quantize(self.mix(from, to, factor))
+    }
+
+    /// Same as [`ColorSpace::interpolate`] but returns unrounded sRGB channels in range
+    /// `0.0..=255.0`
+    pub(crate) fn mix(&self, from: Color, to: Color, factor: f32) -> [f32; 3] {
+        let factor = factor.clamp(0.0, 1.0);
+        match self {
+            ColorSpace::Srgb => lerp3(to_rgb(from), to_rgb(to), factor),
+            ColorSpace::LinearRgb => {
+                let (from, to) = (to_linear(from), to_linear(to));
+                from_linear(lerp3(from, to, factor))
+            }
+            ColorSpace::Oklab => {
+                let (from, to) = (to_oklab(from), to_oklab(to));
+                from_oklab(lerp3(from, to, factor))
+            }
+            ColorSpace::Hsl => {
+                let (from, to) = (to_hsl(from), to_hsl(to));
+                from_hsl(lerp_hsl(from, to, factor))
+            }
+        }
+    }
+
+    /// Converts sRGB channels to color. sRGB channels are truncated as plain interpolation has
+    /// always done, the other spaces round them.
+    pub(crate) fn quantize(&self, [r, g, b]: [f32; 3]) -> Color {
+        let quantize = |c: f32| -> u8 {
+            let c = c.clamp(0.0, 255.0);
+            match self {
+                ColorSpace::Srgb => c as u8,
+                _ => c.round() as u8,
+            }
+        };
+        Color::new(quantize(r), quantize(g), quantize(b))
+    }
+}
+
+/// Returns sRGB channels of color in range `0.0..=255.0`
+pub(crate) fn to_rgb(color: Color) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+fn lerp(start: f32, end: f32, factor: f32) -> f32 {
+    start + (end - start) * factor
+}
+
+fn lerp3(from: [f32; 3], to: [f32; 3], factor: f32) -> [f32; 3] {
+    [
+        lerp(from[0], to[0], factor),
+        lerp(from[1], to[1], factor),
+        lerp(from[2], to[2], factor),
+    ]
+}
+
+/* sRGB transfer function */
+fn decode_channel(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode_channel(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    c * 255.0
+}
+
+fn to_linear(color: Color) -> [f32; 3] {
+    [
+        decode_channel(color.r),
+        decode_channel(color.g),
+        decode_channel(color.b),
+    ]
+}
+
+fn from_linear([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [encode_channel(r), encode_channel(g), encode_channel(b)]
+}
+
+/* OKLab, see https://bottosson.github.io/posts/oklab/ */
+fn to_oklab(color: Color) -> [f32; 3] {
+    let [r, g, b] = to_linear(color);
+
+    let l = (0.41222147 * r + 0.53633254 * g + 0.051445993 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+    let s = (0.08830246 * r + 0.28171884 * g + 0.6299787 * b).cbrt();
+
+    [
+        0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+    ]
+}
+
+fn from_oklab([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+    let l = (lightness + 0.39633778 * a + 0.21580376 * b).powi(3);
+    let m = (lightness - 0.105561346 * a - 0.06385417 * b).powi(3);
+    let s = (lightness - 0.08948418 * a - 1.2914855 * b).powi(3);
+
+    from_linear([
+        4.0767417 * l - 3.3077116 * m + 0.23096993 * s,
+        -1.268438 * l + 2.6097574 * m - 0.3413194 * s,
+        -0.0041960863 * l - 0.7034186 * m + 1.7076147 * s,
+    ])
+}
+
+/* HSL with hue in degrees, saturation and lightness in range 0.0..=1.0 */
+fn to_hsl(color: Color) -> [f32; 3] {
+    let (r, g, b) = (
+        color.r as f32 / 255.0,
+        color.g as f32 / 255.0,
+        color.b as f32 / 255.0,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+
+    if delta == 0.0 {
+        return [f32::NAN, 0.0, lightness];
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+
+    [hue * 60.0, saturation, lightness]
+}
+
+pub(crate) fn from_hsl([hue, saturation, lightness]: [f32; 3]) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+
+    [(r + m) * 255.0, (g + m) * 255.0, (b + m) * 255.0]
+}
+
+/// Interpolates HSL colors along the shorter hue arc. Grays have no hue (`NAN`) and take the
+/// hue of the other end.
+fn lerp_hsl(from: [f32; 3], to: [f32; 3], factor: f32) -> [f32; 3] {
+    let hue = match (from[0].is_nan(), to[0].is_nan()) {
+        (true, true) => 0.0,
+        (true, false) => to[0],
+        (false, true) => from[0],
+        (false, false) => {
+            let delta = (to[0] - from[0] + 180.0).rem_euclid(360.0) - 180.0;
+            from[0] + delta * factor
+        }
+    };
+
+    [
+        hue,
+        lerp(from[1], to[1], factor),
+        lerp(from[2], to[2], factor),
+    ]
+}
+
+fn invalid(s: &str) -> LomenError {
+    LomenError::InvalidArgument(format!(
+        "unknown color space `{}`, expected srgb, linear-rgb, oklab or hsl",
+        s
+    ))
+}
+
+impl FromStr for ColorSpace {
+    type Err = LomenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "srgb" | "rgb" => Ok(ColorSpace::Srgb),
+            "linear-rgb" | "linear" => Ok(ColorSpace::LinearRgb),
+            "oklab" => Ok(ColorSpace::Oklab),
+            "hsl" => Ok(ColorSpace::Hsl),
+            _ => Err(invalid(s)),
+        }
+    }
+}
+
+impl Display for ColorSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorSpace::Srgb => write!(f, "srgb"),
+            ColorSpace::LinearRgb => write!(f, "linear-rgb"),
+            ColorSpace::Oklab => write!(f, "oklab"),
+            ColorSpace::Hsl => write!(f, "hsl"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL: [ColorSpace; 4] = [
+        ColorSpace::Srgb,
+        ColorSpace::LinearRgb,
+        ColorSpace::Oklab,
+        ColorSpace::Hsl,
+    ];
+
+    fn midpoint(space: ColorSpace, from: u64, to: u64) -> Color {
+        space.interpolate(Color::from(from), Color::from(to), 0.5)
+    }
+
+    #[test]
+    fn test_interpolate_ends() {
+        let (from, to) = (Color::from(0xFF8040), Color::from(0x2050C0));
+        for space in ALL {
+            assert_eq!(from, space.interpolate(from, to, 0.0), "{}", space);
+            assert_eq!(to, space.interpolate(from, to, 1.0), "{}", space);
+            assert_eq!(to, space.interpolate(from, to, 3.0), "{}", space);
+        }
+    }
+
+    #[test]
+    fn test_srgb_midpoint() {
+        assert_eq!(
+            Color::from(0x7F7F7F),
+            midpoint(ColorSpace::Srgb, 0xFF0000, 0x00FFFF)
+        );
+    }
+
+    #[test]
+    fn test_linear_rgb_midpoint() {
+        assert_eq!(
+            Color::from(0xBCBCBC),
+            midpoint(ColorSpace::LinearRgb, 0xFF0000, 0x00FFFF)
+        );
+        assert_eq!(
+            Color::from(0xBC0000),
+            midpoint(ColorSpace::LinearRgb, 0x000000, 0xFF0000)
+        );
+    }
+
+    #[test]
+    fn test_oklab_midpoint() {
+        let mid = midpoint(ColorSpace::Oklab, 0xFF0000, 0x00FFFF);
+        let srgb = midpoint(ColorSpace::Srgb, 0xFF0000, 0x00FFFF);
+        let lightness = |c: Color| to_oklab(c)[0];
+
+        // Lightness of the midpoint lies between lightness of ends instead of dipping below both
+        assert!(lightness(mid) > lightness(srgb));
+        assert!(lightness(mid) > lightness(Color::from(0xFF0000)));
+        assert!(lightness(mid) < lightness(Color::from(0x00FFFF)));
+        assert_eq!(
+            Color::from(0xFFFFFF),
+            ColorSpace::Oklab.quantize(from_oklab(to_oklab(Color::from(0xFFFFFF))))
+        );
+    }
+
+    #[test]
+    fn test_hsl_midpoint() {
+        // Red to blue goes through magenta rather than green
+        assert_eq!(
+            Color::from(0xFF00FF),
+            midpoint(ColorSpace::Hsl, 0xFF0000, 0x0000FF)
+        );
+        // Hue wraps around 360 degrees
+        assert_eq!(
+            Color::from(0xFF0000),
+            midpoint(ColorSpace::Hsl, 0xFF00FF, 0xFFFF00)
+        );
+        // Gray takes hue of the other end
+        assert_eq!(
+            Color::from(0xBF4040),
+            midpoint(ColorSpace::Hsl, 0x808080, 0xFF0000)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        for space in ALL {
+            assert_eq!(space, space.to_string().parse().unwrap());
+        }
+        assert_eq!(ColorSpace::Oklab, " OKLab ".parse().unwrap());
+        assert!(matches!(
+            "cmyk".parse::<ColorSpace>(),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+}
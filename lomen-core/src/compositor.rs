@@ -0,0 +1,480 @@
+use crate::color::{Color, LightingColors};
+use crate::effect::Effect;
+use crate::error::LomenError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// How layer colors are combined with the colors below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Layer color covers the colors below
+    #[default]
+    Normal,
+    /// Channels are summed, brightening the colors below
+    Add,
+    /// Channels are multiplied, darkening the colors below
+    Multiply,
+    /// Inverted channels are multiplied, brightening the colors below
+    Screen,
+}
+
+impl BlendMode {
+    /// Blends channels in range `0.0..=1.0`
+    fn blend(&self, below: f32, layer: f32) -> f32 {
+        match self {
+            BlendMode::Normal => layer,
+            BlendMode::Add => (below + layer).min(1.0),
+            BlendMode::Multiply => below * layer,
+            BlendMode::Screen => 1.0 - (1.0 - below) * (1.0 - layer),
+        }
+    }
+}
+
+impl FromStr for BlendMode {
+    type Err = LomenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "normal" => Ok(BlendMode::Normal),
+            "add" => Ok(BlendMode::Add),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            _ => Err(LomenError::InvalidArgument(format!(
+                "unknown blend mode `{}`, expected normal, add, multiply or screen",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for BlendMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BlendMode::Normal => write!(f, "normal"),
+            BlendMode::Add => write!(f, "add"),
+            BlendMode::Multiply => write!(f, "multiply"),
+            BlendMode::Screen => write!(f, "screen"),
+        }
+    }
+}
+
+/// Opacity of layer in every zone, 0 is transparent and 1 opaque
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneAlpha {
+    pub right: f32,
+    pub center: f32,
+    pub left: f32,
+    pub game: f32,
+}
+
+impl ZoneAlpha {
+    pub const OPAQUE: Self = Self::uniform(1.0);
+
+    pub const fn uniform(alpha: f32) -> Self {
+        Self {
+            right: alpha,
+            center: alpha,
+            left: alpha,
+            game: alpha,
+        }
+    }
+}
+
+impl Default for ZoneAlpha {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
+
+/// Position of layer in the stack, layers are composited from the bottom one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LayerSlot {
+    /// Base profile colors
+    Base,
+    /// Running effect
+    Effect,
+    /// Transient notification overlay
+    Notification,
+}
+
+/// Layer colors fixed over time
+struct StaticColors(LightingColors);
+
+impl Effect for StaticColors {
+    fn frame(&mut self, _t: Duration) -> LightingColors {
+        self.0
+    }
+}
+
+/// Source of colors composited with the layers below. Zones the source leaves unset are
+/// transparent.
+pub struct Layer {
+    source: Box<dyn Effect>,
+    alpha: ZoneAlpha,
+    blend: BlendMode,
+    duration: Option<Duration>,
+}
+
+impl Layer {
+    /// Creates opaque layer playing `source` until removed
+    pub fn new(source: Box<dyn Effect>) -> Self {
+        Self {
+            source,
+            alpha: ZoneAlpha::OPAQUE,
+            blend: BlendMode::Normal,
+            duration: None,
+        }
+    }
+
+    /// Creates opaque layer of fixed colors
+    pub fn colors(colors: LightingColors) -> Self {
+        Self::new(Box::new(StaticColors(colors)))
+    }
+
+    /// Sets opacity of all zones, values out of `0.0..=1.0` are clamped
+    pub fn with_alpha(self, alpha: f32) -> Self {
+        self.with_zone_alpha(ZoneAlpha::uniform(alpha))
+    }
+
+    /// Sets opacity of every zone, values out of `0.0..=1.0` are clamped
+    pub fn with_zone_alpha(mut self, alpha: ZoneAlpha) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Removes the layer after `duration`, revealing the layers below
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    fn alphas(&self) -> [f32; 4] {
+        [
+            self.alpha.right,
+            self.alpha.center,
+            self.alpha.left,
+            self.alpha.game,
+        ]
+        .map(|alpha| {
+            if alpha.is_nan() {
+                0.0
+            } else {
+                alpha.clamp(0.0, 1.0)
+            }
+        })
+    }
+}
+
+/// Layer in the stack with compositor time it was added at
+struct PlacedLayer {
+    layer: Layer,
+    start: Duration,
+}
+
+/// Stack of layers composited into the colors written to the keyboard.
+///
+/// Layers are composited from [`LayerSlot::Base`] up. A zone left unset by all layers stays
+/// unset, so its color is not changed; a translucent layer over unset zone is blended with
+/// black. Every layer source gets time since the layer was added.
+#[derive(Default)]
+pub struct Compositor {
+    layers: BTreeMap<LayerSlot, PlacedLayer>,
+    /// Time of the last composited frame
+    now: Duration,
+    /// Compositor time the current playback started at
+    origin: Duration,
+}
+
+/// Compositor shared between the animation playing it and its clients
+pub type SharedCompositor = Arc<Mutex<Compositor>>;
+
+impl Compositor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts `layer` into `slot` replacing the previous one
+    pub fn set(&mut self, slot: LayerSlot, layer: Layer) {
+        let start = self.now;
+        self.layers.insert(slot, PlacedLayer { layer, start });
+    }
+
+    /// Removes layer from `slot`, returns whether there was one
+    pub fn remove(&mut self, slot: LayerSlot) -> bool {
+        self.layers.remove(&slot).is_some()
+    }
+
+    pub fn contains(&self, slot: LayerSlot) -> bool {
+        self.layers.contains_key(&slot)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Continues compositor time from the last frame when it is played again with time
+    /// starting from zero. Layers keep running from where they were paused.
+    pub fn resume(&mut self) {
+        self.origin = self.now;
+    }
+
+    /// Returns colors of all layers at compositor time `t`. Expired layers are removed.
+    pub fn compose(&mut self, t: Duration) -> LightingColors {
+        self.now = t;
+        self.layers.retain(|_, placed| {
+            let end = placed
+                .layer
+                .duration
+                .and_then(|duration| placed.start.checked_add(duration));
+            end.is_none_or(|end| t < end)
+        });
+
+        let mut zones: [Option<[f32; 3]>; 4] = [None; 4];
+        for placed in self.layers.values_mut() {
+            let colors = placed.layer.source.frame(t.saturating_sub(placed.start));
+            let alphas = placed.layer.alphas();
+            let layer = [colors.right, colors.center, colors.left, colors.game];
+
+            for ((zone, color), alpha) in zones.iter_mut().zip(layer).zip(alphas) {
+                if let Some(color) = color {
+                    *zone = Some(composite(
+                        zone.unwrap_or_default(),
+                        channels(color),
+                        alpha,
+                        placed.layer.blend,
+                    ));
+                }
+            }
+        }
+
+        let [right, center, left, game] = zones.map(|zone| zone.map(to_color));
+        LightingColors {
+            right,
+            center,
+            left,
+            game,
+        }
+    }
+}
+
+impl Effect for Compositor {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        let t = self.origin + t;
+        self.compose(t)
+    }
+}
+
+impl Effect for SharedCompositor {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        self.lock().unwrap_or_else(PoisonError::into_inner).frame(t)
+    }
+}
+
+fn channels(color: Color) -> [f32; 3] {
+    [color.r, color.g, color.b].map(|c| c as f32 / 255.0)
+}
+
+fn to_color(channels: [f32; 3]) -> Color {
+    let [r, g, b] = channels.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Color::new(r, g, b)
+}
+
+fn composite(below: [f32; 3], layer: [f32; 3], alpha: f32, blend: BlendMode) -> [f32; 3] {
+    let mut result = below;
+    for (i, channel) in result.iter_mut().enumerate() {
+        *channel += (blend.blend(below[i], layer[i]) - below[i]) * alpha;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::effect::{EffectKind, EffectParams};
+
+    fn colors(color: u64) -> LightingColors {
+        LightingColors {
+            right: Some(Color::from(color)),
+            center: Some(Color::from(color)),
+            left: Some(Color::from(color)),
+            game: Some(Color::from(color)),
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_parse_blend_mode() {
+        for blend in [
+            BlendMode::Normal,
+            BlendMode::Add,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+        ] {
+            assert_eq!(blend, blend.to_string().parse().unwrap());
+        }
+        assert!("overlay".parse::<BlendMode>().is_err());
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let blend = |blend: BlendMode| {
+            let mut compositor = Compositor::new();
+            compositor.set(LayerSlot::Base, Layer::colors(colors(0x804020)));
+            compositor.set(
+                LayerSlot::Effect,
+                Layer::colors(colors(0x808080)).with_blend(blend),
+            );
+            compositor.compose(Duration::ZERO).right.unwrap()
+        };
+
+        assert_eq!(Color::from(0x808080), blend(BlendMode::Normal));
+        assert_eq!(Color::from(0xFFC0A0), blend(BlendMode::Add));
+        assert_eq!(Color::from(0x402010), blend(BlendMode::Multiply));
+        assert_eq!(Color::from(0xC0A090), blend(BlendMode::Screen));
+    }
+
+    #[test]
+    fn test_alpha() {
+        let mut compositor = Compositor::new();
+        compositor.set(LayerSlot::Base, Layer::colors(colors(0x0000FF)));
+        compositor.set(
+            LayerSlot::Notification,
+            Layer::colors(colors(0xFF0000)).with_zone_alpha(ZoneAlpha {
+                right: 1.0,
+                center: 0.5,
+                left: 0.0,
+                game: 2.0,
+            }),
+        );
+
+        let result = compositor.compose(Duration::ZERO);
+        assert_eq!(Some(Color::from(0xFF0000)), result.right);
+        assert_eq!(Some(Color::from(0x800080)), result.center);
+        assert_eq!(Some(Color::from(0x0000FF)), result.left);
+        assert_eq!(Some(Color::from(0xFF0000)), result.game);
+    }
+
+    #[test]
+    fn test_unset_zones() {
+        let mut compositor = Compositor::new();
+        assert_eq!(
+            LightingColors::default(),
+            compositor.compose(Duration::ZERO)
+        );
+
+        compositor.set(
+            LayerSlot::Base,
+            Layer::colors(LightingColors {
+                right: Some(Color::from(0x00FF00)),
+                ..Default::default()
+            }),
+        );
+        compositor.set(
+            LayerSlot::Effect,
+            Layer::colors(LightingColors {
+                center: Some(Color::from(0xFFFFFF)),
+                ..Default::default()
+            })
+            .with_alpha(0.5),
+        );
+
+        let result = compositor.compose(Duration::ZERO);
+        assert_eq!(Some(Color::from(0x00FF00)), result.right);
+        assert_eq!(Some(Color::from(0x808080)), result.center);
+        assert_eq!(None, result.left);
+    }
+
+    #[test]
+    fn test_notification_reveals_effect() {
+        let mut compositor = Compositor::new();
+        let effect = EffectKind::Strobe
+            .create(&EffectParams {
+                colors: vec![Color::from(0x00FF00)],
+                speed: 1.0,
+                ..Default::default()
+            })
+            .unwrap();
+        compositor.set(LayerSlot::Effect, Layer::new(effect));
+        let before = compositor.compose(ms(100));
+
+        compositor.set(
+            LayerSlot::Notification,
+            Layer::colors(colors(0xFF0000)).with_duration(ms(500)),
+        );
+        assert_eq!(colors(0xFF0000), compositor.compose(ms(500)));
+        assert!(compositor.contains(LayerSlot::Notification));
+
+        // The effect has kept running under the notification
+        assert_eq!(before, compositor.compose(ms(1100)));
+        assert!(!compositor.contains(LayerSlot::Notification));
+    }
+
+    #[test]
+    fn test_layer_time() {
+        let mut compositor = Compositor::new();
+        compositor.compose(ms(1000));
+        let effect = EffectKind::Breathing
+            .create(&EffectParams {
+                colors: vec![Color::from(0xFFFFFF)],
+                speed: 1.0,
+                ..Default::default()
+            })
+            .unwrap();
+        compositor.set(LayerSlot::Effect, Layer::new(effect));
+
+        // Layer added later starts from its own beginning
+        let mut breathing = EffectKind::Breathing
+            .create(&EffectParams {
+                colors: vec![Color::from(0xFFFFFF)],
+                speed: 1.0,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(breathing.frame(ms(250)), compositor.compose(ms(1250)));
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut compositor = Compositor::new();
+        compositor.set(
+            LayerSlot::Notification,
+            Layer::colors(colors(0xFF0000)).with_duration(ms(500)),
+        );
+        compositor.frame(ms(400));
+
+        // Played again from zero, the notification has 100 ms left
+        compositor.resume();
+        assert_eq!(colors(0xFF0000), compositor.frame(ms(50)));
+        assert!(compositor.frame(ms(100)).right.is_none());
+        assert!(compositor.is_empty());
+    }
+
+    #[test]
+    fn test_longest_layer_duration() {
+        let mut compositor = Compositor::new();
+        compositor.compose(ms(100));
+        compositor.set(
+            LayerSlot::Notification,
+            Layer::colors(colors(0xFF0000)).with_duration(Duration::MAX),
+        );
+
+        assert_eq!(colors(0xFF0000), compositor.compose(ms(200)));
+        assert!(compositor.contains(LayerSlot::Notification));
+    }
+}
@@ -1,229 +1,374 @@
-use crate::color::{Color, LightingColors};
-use crate::transition::LightingColorsTransition;
-use error::Error;
-use log::debug;
-use std::error;
-use std::thread::sleep;
-use std::time::Duration;
-use wmi::{IWbemClassWrapper, Variant, WMIConnection};
-
-static SIGN: [u8; 4] = [83, 69, 67, 85];
-
-/* Command constants */
-const CMD_COMMON: u32 = 131081;
-const CMD_GAMING: u32 = 131080;
-
-/* Command type constants */
-const CMD_TYPE_GET_PLATFORM_INFO: u32 = 1;
-const CMD_TYPE_GET_ZONE_COLORS: u32 = 2;
-const CMD_TYPE_SET_ZONE_COLORS: u32 = 3;
-// const CMD_TYPE_STATUS: u32 = 4;
-// const CMD_TYPE_SET_BRIGHTNESS: u32 = 5;
-// const CMD_TYPE_SET_LIGHT_BAR_COLORS: u32 = 11;
-const CMD_TYPE_GET_KEYBOARD_TYPE: u32 = 43;
-
-// /* Lighting levels */
-// const LIGHTING_LEVEL_ON: u8 = 228;
-// const LIGHTING_LEVEL_OFF: u8 = 100;
-
-/* Zone indices */
-const RIGHT_ZONE_INDEX: usize = 0;
-const CENTER_ZONE_INDEX: usize = 1;
-const LEFT_ZONE_INDEX: usize = 2;
-const GAME_ZONE_INDEX: usize = 3;
-
-fn bytes_to_variant(bytes: &[u8]) -> Variant {
-    Variant::Array(bytes.iter().copied().map(Variant::UI1).collect())
-}
-
-fn variant_to_bytes(v: Variant) -> Result<Vec<u8>, Box<dyn Error>> {
-    match v {
-        Variant::Array(vec) => {
-            let mut out = Vec::with_capacity(vec.len());
-            for (i, item) in vec.into_iter().enumerate() {
-                match item {
-                    Variant::UI1(b) => out.push(b),
-                    other => {
-                        return Err(
-                            format!("Element {} has unsupported type: {:?}", i, other).into()
-                        );
-                    }
-                }
-            }
-            Ok(out)
-        }
-        other => Err(format!("Variant::Array expected, but {:?} found", other).into()),
-    }
+use crate::backend::Backend;
+use crate::capabilities::Capabilities;
+use crate::clock::{Clock, SystemClock};
+use crate::color::LightingColors;
+use crate::error::LomenError;
+use crate::keyboard::KeyboardType;
+use crate::protocol::{
+    BiosRequest, CMD_COMMON, CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE, CMD_TYPE_GET_LIGHT_BAR_COLORS,
+    CMD_TYPE_GET_PLATFORM_INFO, CMD_TYPE_GET_ZONE_COLORS, CMD_TYPE_SET_BRIGHTNESS,
+    CMD_TYPE_SET_LIGHT_BAR_COLORS, CMD_TYPE_SET_ZONE_COLORS, CMD_TYPE_STATUS, LIGHTING_ON_FLAG,
+    MAX_BRIGHTNESS,
+};
+use crate::response::{LightingStatus, PlatformInfo, ZoneColors, parse_keyboard_type};
+use crate::scheduler::{FrameScheduler, FrameStats};
+use crate::transition::{LightingColorsTransition, TransitionOptions};
+
+/// Executes request returning response data or error if BIOS rejected it
+fn execute(backend: &mut dyn Backend, request: BiosRequest) -> Result<Vec<u8>, LomenError> {
+    backend.execute(&request)?.into_data()
 }
 
-fn rgb_offsets(zone_index: usize) -> (usize, usize, usize) {
-    let offset = 25 + zone_index * 3;
-    (offset, offset + 1, offset + 2)
+/// Returns keyboard type
+pub fn get_keyboard_type(backend: &mut dyn Backend) -> Result<KeyboardType, LomenError> {
+    let data = execute(
+        backend,
+        BiosRequest::new(CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE),
+    )?;
+    parse_keyboard_type(&data)
 }
 
-fn get_zone_color(data: &[u8], zone_index: usize) -> Color {
-    let (ri, gi, bi) = rgb_offsets(zone_index);
-    Color::new(data[ri], data[gi], data[bi])
+/// Returns decoded platform info
+pub fn get_platform_info(backend: &mut dyn Backend) -> Result<PlatformInfo, LomenError> {
+    let data = execute(
+        backend,
+        BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_PLATFORM_INFO),
+    )?;
+    PlatformInfo::parse(data)
 }
 
-fn set_zone_color(data: &mut [u8], zone_index: usize, color: Option<Color>) {
-    if let Some(c) = color {
-        let (ri, gi, bi) = rgb_offsets(zone_index);
-        data[ri] = c.r;
-        data[gi] = c.g;
-        data[bi] = c.b;
-    }
+/// Checks whether keyboard lighting is supported
+pub fn is_lighting_supported(backend: &mut dyn Backend) -> Result<bool, LomenError> {
+    Ok(get_platform_info(backend)?.lighting_supported)
 }
 
-fn execute_wmi_command(
-    command_code: u32,
-    command_type: u32,
-    data: Option<&[u8]>,
-) -> Result<Vec<u8>, Box<dyn Error>> {
-    debug!("Executing command: {:?}, type: {:?}", command_code, command_type);
-
-    let wmi_con = WMIConnection::with_namespace_path(r"root\wmi")?;
+/// Checks whether the device has a light bar
+pub fn is_light_bar_supported(backend: &mut dyn Backend) -> Result<bool, LomenError> {
+    Ok(get_platform_info(backend)?.light_bar_supported)
+}
 
-    let (payload, payload_size) = match data {
-        Some(d) => {
-            let i = d.len() as u32;
-            (bytes_to_variant(d.into()), i)
-        }
-        None => (Variant::Null, 0u32),
+/// Returns lighting features of the device collected from platform info, keyboard type and
+/// lighting status
+pub fn get_capabilities(backend: &mut dyn Backend) -> Result<Capabilities, LomenError> {
+    let platform_info = get_platform_info(backend)?;
+    let keyboard_type = get_keyboard_type(backend)?;
+    let brightness_supported = match get_status(backend) {
+        Ok(_) => true,
+        Err(LomenError::ReturnCode(code)) if code.is_not_supported() => false,
+        Err(LomenError::Unsupported(_)) => false,
+        Err(e) => return Err(e),
     };
 
-    let in_data = wmi_con.get_object("hpqBDataIn")?;
-    in_data.put_property("Sign", bytes_to_variant(&SIGN))?;
-    in_data.put_property("Command", Variant::UI4(command_code))?;
-    in_data.put_property("CommandType", Variant::UI4(command_type))?;
-    in_data.put_property("Size", Variant::UI4(payload_size))?;
-    in_data.put_property("hpqBData", payload)?;
-
-    let in_params = wmi_con
-        .get_object("hpqBIntM")?
-        .get_method("hpqBIOSInt128")?
-        .unwrap()
-        .spawn_instance()?;
-    in_params.put_property("InData", in_data)?;
-
-    let out_params = wmi_con
-        .exec_method(
-            r"hpqBIntM.InstanceName='ACPI\PNP0C14\0_0'",
-            "hpqBIOSInt128",
-            Some(&in_params),
-        )?
-        .unwrap();
+    Ok(Capabilities::new(
+        &platform_info,
+        keyboard_type,
+        brightness_supported,
+    ))
+}
 
-    let out_data: IWbemClassWrapper = out_params.get_property("OutData")?.try_into()?;
+/// Reads colors buffer with `get_type` command
+pub(crate) fn read_zone_colors(
+    backend: &mut dyn Backend,
+    get_type: u32,
+) -> Result<ZoneColors, LomenError> {
+    let data = execute(backend, BiosRequest::new(CMD_COMMON, get_type))?;
+    ZoneColors::parse(data)
+}
 
-    let return_code: u32 = out_data.get_property("rwReturnCode")?.try_into()?;
-    if return_code != 0 {
-        return Err(format!("Invalid return code: {}", return_code).into());
-    }
+/// Writes whole colors buffer with `set_type` command
+pub(crate) fn write_zone_buffer(
+    backend: &mut dyn Backend,
+    set_type: u32,
+    zone_colors: &ZoneColors,
+) -> Result<(), LomenError> {
+    execute(
+        backend,
+        BiosRequest::with_data(CMD_COMMON, set_type, zone_colors.as_bytes()),
+    )?;
 
-    Ok(variant_to_bytes(out_data.get_property("Data")?)?)
+    Ok(())
 }
 
-/// Returns keyboard type
-pub fn get_keyboard_type() -> Result<u8, Box<dyn Error>> {
-    let data = execute_wmi_command(CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE, None)?;
-    Ok(data[0])
+/// Reads colors buffer with `get_type` command, updates it and writes back with `set_type` one
+fn write_zone_colors(
+    backend: &mut dyn Backend,
+    get_type: u32,
+    set_type: u32,
+    colors: &LightingColors,
+) -> Result<(), LomenError> {
+    let mut zone_colors = read_zone_colors(backend, get_type)?;
+    zone_colors.set_colors(colors);
+    write_zone_buffer(backend, set_type, &zone_colors)
 }
 
-/// Checks whether keyboard lighting is supported
-pub fn is_lighting_supported() -> Result<bool, Box<dyn Error>> {
-    let data = execute_wmi_command(CMD_COMMON, CMD_TYPE_GET_PLATFORM_INFO, None)?;
-    Ok((data[0] & 1) == 1)
+/// Returns current keyboard lighting colors
+pub fn get_colors(backend: &mut dyn Backend) -> Result<LightingColors, LomenError> {
+    Ok(read_zone_colors(backend, CMD_TYPE_GET_ZONE_COLORS)?.colors())
 }
 
-/// Returns current keyboard lighting colors
-pub fn get_colors() -> Result<LightingColors, Box<dyn Error>> {
-    let result = execute_wmi_command(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS, None)?;
-    let data = result.as_ref();
-
-    Ok(LightingColors {
-        right: get_zone_color(data, RIGHT_ZONE_INDEX).into(),
-        center: get_zone_color(data, CENTER_ZONE_INDEX).into(),
-        left: get_zone_color(data, LEFT_ZONE_INDEX).into(),
-        game: get_zone_color(data, GAME_ZONE_INDEX).into(),
-    })
+/// Sets keyboard lighting colors. Unset zones keep their current colors.
+pub fn set_colors(backend: &mut dyn Backend, colors: &LightingColors) -> Result<(), LomenError> {
+    write_zone_colors(
+        backend,
+        CMD_TYPE_GET_ZONE_COLORS,
+        CMD_TYPE_SET_ZONE_COLORS,
+        colors,
+    )
+}
+
+/// Returns current light bar colors
+pub fn get_light_bar_colors(backend: &mut dyn Backend) -> Result<LightingColors, LomenError> {
+    Ok(read_zone_colors(backend, CMD_TYPE_GET_LIGHT_BAR_COLORS)?.colors())
 }
 
-/// Sets keyboard lighting colors
-pub fn set_colors(colors: &LightingColors) -> Result<(), Box<dyn Error>> {
-    let mut result = execute_wmi_command(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS, None)?;
-    let data = result.as_mut();
+/// Sets light bar colors
+pub fn set_light_bar_colors(
+    backend: &mut dyn Backend,
+    colors: &LightingColors,
+) -> Result<(), LomenError> {
+    write_zone_colors(
+        backend,
+        CMD_TYPE_GET_LIGHT_BAR_COLORS,
+        CMD_TYPE_SET_LIGHT_BAR_COLORS,
+        colors,
+    )
+}
 
-    set_zone_color(data, RIGHT_ZONE_INDEX, colors.right);
-    set_zone_color(data, CENTER_ZONE_INDEX, colors.center);
-    set_zone_color(data, LEFT_ZONE_INDEX, colors.left);
-    set_zone_color(data, GAME_ZONE_INDEX, colors.game);
+/// Returns keyboard lighting status
+pub fn get_status(backend: &mut dyn Backend) -> Result<LightingStatus, LomenError> {
+    let data = execute(backend, BiosRequest::new(CMD_COMMON, CMD_TYPE_STATUS))?;
+    LightingStatus::parse(&data)
+}
 
-    execute_wmi_command(CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS, Some(data))?;
+fn set_lighting_level(backend: &mut dyn Backend, level: u8) -> Result<(), LomenError> {
+    execute(
+        backend,
+        BiosRequest::with_data(CMD_COMMON, CMD_TYPE_SET_BRIGHTNESS, &[level]),
+    )?;
 
     Ok(())
 }
 
-/// Smoothly changes keyboard lighting colors
+/// Returns keyboard backlight brightness in percent
+pub fn get_brightness(backend: &mut dyn Backend) -> Result<u8, LomenError> {
+    Ok(get_status(backend)?.brightness)
+}
+
+/// Sets keyboard backlight brightness in percent keeping lighting turned on or off
+pub fn set_brightness(backend: &mut dyn Backend, brightness: u8) -> Result<(), LomenError> {
+    if brightness > MAX_BRIGHTNESS {
+        return Err(LomenError::InvalidArgument(format!(
+            "brightness must be in range 0..={}, but {} given",
+            MAX_BRIGHTNESS, brightness
+        )));
+    }
+
+    let level = get_status(backend)?.level;
+    set_lighting_level(backend, (level & LIGHTING_ON_FLAG) | brightness)
+}
+
+/// Turns keyboard backlight on or off keeping zone colors and brightness
+pub fn set_lighting_enabled(backend: &mut dyn Backend, enabled: bool) -> Result<(), LomenError> {
+    let level = get_status(backend)?.level;
+    let level = if enabled {
+        level | LIGHTING_ON_FLAG
+    } else {
+        level & !LIGHTING_ON_FLAG
+    };
+
+    set_lighting_level(backend, level)
+}
+
+fn transit(
+    backend: &mut dyn Backend,
+    get_type: u32,
+    set_type: u32,
+    to_colors: &LightingColors,
+    options: &TransitionOptions,
+    clock: &dyn Clock,
+) -> Result<FrameStats, LomenError> {
+    let scheduler = FrameScheduler::new(options.duration, options.fps)?;
+    let from_colors = read_zone_colors(backend, get_type)?.colors();
+    let transition = LightingColorsTransition::new(from_colors, *to_colors).with_options(options);
+
+    scheduler.run_with_clock(clock, |progress| {
+        write_zone_colors(backend, get_type, set_type, &transition.sample(progress))
+    })
+}
+
+/// Smoothly changes keyboard lighting colors starting from the colors read from the device.
+/// Unset zones keep their current colors as with [`set_colors`]. Frames that cannot be written
+/// in time are dropped, target colors are always written last.
 pub fn transit_colors(
+    backend: &mut dyn Backend,
     to_colors: &LightingColors,
-    duration: Duration,
-    fps: u8,
-) -> Result<(), Box<dyn Error>> {
-    let transition = LightingColorsTransition::new(get_colors()?, to_colors.clone(), duration);
-    let delay = duration.div_f32(fps as f32);
+    options: &TransitionOptions,
+) -> Result<FrameStats, LomenError> {
+    transit_colors_with_clock(backend, to_colors, options, &SystemClock::new())
+}
 
-    for colors in transition {
-        set_colors(&colors)?;
-        sleep(delay);
-    }
+/// Same as [`transit_colors`] but takes time from given clock
+pub fn transit_colors_with_clock(
+    backend: &mut dyn Backend,
+    to_colors: &LightingColors,
+    options: &TransitionOptions,
+    clock: &dyn Clock,
+) -> Result<FrameStats, LomenError> {
+    transit(
+        backend,
+        CMD_TYPE_GET_ZONE_COLORS,
+        CMD_TYPE_SET_ZONE_COLORS,
+        to_colors,
+        options,
+        clock,
+    )
+}
 
-    Ok(())
+/// Smoothly changes light bar colors
+pub fn transit_light_bar_colors(
+    backend: &mut dyn Backend,
+    to_colors: &LightingColors,
+    options: &TransitionOptions,
+) -> Result<FrameStats, LomenError> {
+    transit(
+        backend,
+        CMD_TYPE_GET_LIGHT_BAR_COLORS,
+        CMD_TYPE_SET_LIGHT_BAR_COLORS,
+        to_colors,
+        options,
+        &SystemClock::new(),
+    )
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::backend::EmulatorBackend;
+    use crate::clock::ManualClock;
+    use crate::color::Color;
+    use crate::error::ReturnCode;
+    use crate::protocol::{
+        BiosResponse, LIGHTING_LEVEL_OFF, LIGHTING_LEVEL_ON, PLATFORM_LIGHT_BAR_FLAG,
+        PLATFORM_LIGHTING_FLAG,
+    };
+    use std::time::Duration;
+
+    fn backend_with_colors() -> EmulatorBackend {
+        let mut backend = EmulatorBackend::new();
+        let mut buffer = *backend.zone_buffer();
+        buffer[25..37].copy_from_slice(&[
+            0xAA, 0x00, 0x00, 0x00, 0xBB, 0x00, 0x00, 0x00, 0xCC, 0x11, 0x22, 0x33,
+        ]);
+        backend.set_zone_buffer(buffer);
+        backend
+    }
 
     #[test]
     fn test_is_lighting_supported() {
-        let result = is_lighting_supported();
-        assert!(result.is_ok());
+        let mut backend = EmulatorBackend::new();
+        assert!(is_lighting_supported(&mut backend).unwrap());
 
-        println!("Lighting supported: {}", result.unwrap());
+        backend.set_platform_info(0b10);
+        assert!(!is_lighting_supported(&mut backend).unwrap());
     }
 
     #[test]
-    fn test_get_keyboard_type() {
-        let result = get_keyboard_type();
-        assert!(result.is_ok());
+    fn test_get_capabilities() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_platform_info(PLATFORM_LIGHTING_FLAG | PLATFORM_LIGHT_BAR_FLAG);
+        backend.set_keyboard_type(1);
+
+        assert_eq!(
+            Capabilities {
+                lighting_supported: true,
+                light_bar_supported: true,
+                brightness_supported: true,
+                keyboard_type: KeyboardType::WithNumpad,
+                zone_count: 4,
+                per_key_rgb: false,
+            },
+            get_capabilities(&mut backend).unwrap()
+        );
+
+        backend.set_return_code(CMD_TYPE_STATUS, 4);
+        assert!(!get_capabilities(&mut backend).unwrap().brightness_supported);
+
+        backend.set_return_code(CMD_TYPE_STATUS, 6);
+        assert!(matches!(
+            get_capabilities(&mut backend),
+            Err(LomenError::ReturnCode(ReturnCode::AccessDenied))
+        ));
+    }
 
-        println!("Keyboard type: {}", result.unwrap());
+    #[test]
+    fn test_get_keyboard_type() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_keyboard_type(2);
+
+        assert_eq!(
+            KeyboardType::TenKeyLess,
+            get_keyboard_type(&mut backend).unwrap()
+        );
+        assert_eq!(
+            &[(CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE)],
+            backend.history()
+        );
     }
 
     #[test]
     fn test_get_colors() {
-        let result = get_colors();
-        assert!(result.is_ok());
-
-        println!("Colors: {:?}", result);
+        let mut backend = backend_with_colors();
+
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0xAA0000)),
+                center: Some(Color::from(0x00BB00)),
+                left: Some(Color::from(0x0000CC)),
+                game: Some(Color::from(0x112233)),
+            },
+            get_colors(&mut backend).unwrap()
+        );
     }
 
     #[test]
     fn test_set_colors() {
+        let mut backend = backend_with_colors();
         let colors = LightingColors {
             right: Some(Color::from(0xFFFF00)),
             center: None,
             left: Some(Color::from(0x00FF00)),
             game: None,
         };
-        let result = set_colors(&colors);
 
-        assert!(result.is_ok());
+        set_colors(&mut backend, &colors).unwrap();
+
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0xFFFF00)),
+                center: Some(Color::from(0x00BB00)),
+                left: Some(Color::from(0x00FF00)),
+                game: Some(Color::from(0x112233)),
+            },
+            get_colors(&mut backend).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_colors_preserves_other_data() {
+        let mut backend = EmulatorBackend::new();
+        let mut buffer = *backend.zone_buffer();
+        buffer[0] = 0x55;
+        buffer[127] = 0x77;
+        backend.set_zone_buffer(buffer);
+
+        set_colors(&mut backend, &LightingColors::default()).unwrap();
+
+        assert_eq!(&buffer, backend.zone_buffer());
     }
 
     #[test]
     fn test_transit_colors() {
+        let mut backend = backend_with_colors();
         let colors = LightingColors {
             right: Some(Color::from(0x0000FF)),
             center: Some(Color::from(0x00FFFF)),
@@ -231,8 +376,279 @@ mod test {
             game: None,
         };
 
-        let result = transit_colors(&colors, Duration::from_secs(1), 50);
+        transit_colors(
+            &mut backend,
+            &colors,
+            &TransitionOptions::new(Duration::from_millis(100), 50),
+        )
+        .unwrap();
+
+        assert_eq!(
+            LightingColors {
+                game: Some(Color::from(0x112233)),
+                ..colors
+            },
+            get_colors(&mut backend).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_light_bar_colors() {
+        let mut backend = backend_with_colors();
+        backend.set_platform_info(PLATFORM_LIGHTING_FLAG | PLATFORM_LIGHT_BAR_FLAG);
+        assert!(is_light_bar_supported(&mut backend).unwrap());
+
+        let colors = LightingColors {
+            right: Some(Color::from(0x123456)),
+            center: None,
+            left: None,
+            game: Some(Color::from(0xABCDEF)),
+        };
+        set_light_bar_colors(&mut backend, &colors).unwrap();
+        transit_light_bar_colors(
+            &mut backend,
+            &colors,
+            &TransitionOptions::new(Duration::from_millis(20), 50),
+        )
+        .unwrap();
+
+        assert_eq!(
+            LightingColors {
+                center: Some(Color::from(0x000000)),
+                left: Some(Color::from(0x000000)),
+                ..colors
+            },
+            get_light_bar_colors(&mut backend).unwrap()
+        );
+        assert_eq!(
+            Some(Color::from(0x00BB00)),
+            get_colors(&mut backend).unwrap().center
+        );
+    }
+
+    #[test]
+    fn test_light_bar_not_supported() {
+        let mut backend = EmulatorBackend::new();
+        assert!(!is_light_bar_supported(&mut backend).unwrap());
+
+        assert!(matches!(
+            get_light_bar_colors(&mut backend),
+            Err(LomenError::ReturnCode(ReturnCode::NotSupported))
+        ));
+        assert!(matches!(
+            set_light_bar_colors(&mut backend, &LightingColors::default()),
+            Err(LomenError::ReturnCode(ReturnCode::NotSupported))
+        ));
+    }
+
+    #[test]
+    fn test_return_code_error() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_return_code(CMD_TYPE_SET_ZONE_COLORS, 5);
+
+        assert!(get_colors(&mut backend).is_ok());
+        assert!(matches!(
+            set_colors(&mut backend, &LightingColors::default()),
+            Err(LomenError::ReturnCode(ReturnCode::InvalidParameters))
+        ));
+    }
+
+    #[test]
+    fn test_get_status() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_lighting_level(LIGHTING_LEVEL_OFF);
+
+        assert_eq!(
+            LightingStatus {
+                enabled: false,
+                brightness: 100,
+                level: LIGHTING_LEVEL_OFF,
+            },
+            get_status(&mut backend).unwrap()
+        );
+
+        set_lighting_enabled(&mut backend, true).unwrap();
+        assert!(get_status(&mut backend).unwrap().enabled);
+    }
+
+    #[test]
+    fn test_get_brightness() {
+        let mut backend = EmulatorBackend::new();
+        assert_eq!(100, get_brightness(&mut backend).unwrap());
+
+        backend.set_lighting_level(LIGHTING_ON_FLAG | 40);
+        assert_eq!(40, get_brightness(&mut backend).unwrap());
+    }
+
+    #[test]
+    fn test_set_brightness() {
+        let mut backend = EmulatorBackend::new();
+
+        set_brightness(&mut backend, 30).unwrap();
+        assert_eq!(LIGHTING_ON_FLAG | 30, backend.lighting_level());
+
+        set_lighting_enabled(&mut backend, false).unwrap();
+        set_brightness(&mut backend, 70).unwrap();
+        assert_eq!(70, backend.lighting_level());
+
+        assert!(matches!(
+            set_brightness(&mut backend, 101),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_lighting_enabled() {
+        let mut backend = backend_with_colors();
+        let colors = get_colors(&mut backend).unwrap();
+
+        set_lighting_enabled(&mut backend, false).unwrap();
+        assert_eq!(LIGHTING_LEVEL_OFF, backend.lighting_level());
+
+        set_lighting_enabled(&mut backend, true).unwrap();
+        assert_eq!(LIGHTING_LEVEL_ON, backend.lighting_level());
+        assert_eq!(colors, get_colors(&mut backend).unwrap());
+    }
+
+    #[test]
+    fn test_short_responses() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_response_size(CMD_TYPE_GET_PLATFORM_INFO, 0);
+        backend.set_response_size(CMD_TYPE_GET_KEYBOARD_TYPE, 0);
+        backend.set_response_size(CMD_TYPE_GET_ZONE_COLORS, 30);
+        backend.set_response_size(CMD_TYPE_STATUS, 0);
+
+        assert!(matches!(
+            is_lighting_supported(&mut backend),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            get_keyboard_type(&mut backend),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            get_colors(&mut backend),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            set_colors(&mut backend, &LightingColors::default()),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            get_brightness(&mut backend),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(
+            !backend
+                .history()
+                .contains(&(CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS))
+        );
+    }
+
+    #[test]
+    fn test_oversized_responses() {
+        let mut backend = backend_with_colors();
+        backend.set_response_size(CMD_TYPE_GET_ZONE_COLORS, 256);
+
+        assert_eq!(
+            Some(Color::from(0x112233)),
+            get_colors(&mut backend).unwrap().game
+        );
+    }
+
+    #[test]
+    fn test_write_after_oversized_response() {
+        let mut backend = backend_with_colors();
+        backend.set_response_size(CMD_TYPE_GET_ZONE_COLORS, 256);
+        let colors = LightingColors {
+            right: Some(Color::from(0x0000FF)),
+            center: None,
+            left: None,
+            game: None,
+        };
+
+        set_colors(&mut backend, &colors).unwrap();
+        set_colors(
+            &mut backend,
+            &LightingColors {
+                right: None,
+                game: Some(Color::from(0x00FF00)),
+                ..colors
+            },
+        )
+        .unwrap();
+        transit_colors(
+            &mut backend,
+            &LightingColors {
+                center: Some(Color::from(0xFF0000)),
+                ..colors
+            },
+            &TransitionOptions::new(Duration::from_millis(20), 50),
+        )
+        .unwrap();
+
+        let colors = get_colors(&mut backend).unwrap();
+        assert_eq!(Some(Color::from(0x0000FF)), colors.right);
+        assert_eq!(Some(Color::from(0xFF0000)), colors.center);
+        assert_eq!(Some(Color::from(0x00FF00)), colors.game);
+    }
+
+    #[test]
+    fn test_slow_backend() {
+        let mut backend = EmulatorBackend::new();
+        backend.set_delay(Duration::from_millis(10));
+
+        assert!(get_keyboard_type(&mut backend).is_ok());
+    }
+
+    /// Backend advancing manual clock by fixed delay on every request
+    struct SlowBackend<'a> {
+        backend: EmulatorBackend,
+        clock: &'a ManualClock,
+        delay: Duration,
+    }
+
+    impl Backend for SlowBackend<'_> {
+        fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+            self.clock.advance(self.delay);
+            self.backend.execute(request)
+        }
+    }
+
+    #[test]
+    fn test_transit_colors_on_slow_backend() {
+        let clock = ManualClock::new();
+        let mut backend = SlowBackend {
+            backend: backend_with_colors(),
+            clock: &clock,
+            delay: Duration::from_millis(25),
+        };
+        let colors = LightingColors {
+            right: Some(Color::from(0x0000FF)),
+            center: None,
+            left: None,
+            game: None,
+        };
+
+        let stats = transit_colors_with_clock(
+            &mut backend,
+            &colors,
+            &TransitionOptions::new(Duration::from_millis(100), 100),
+            &clock,
+        )
+        .unwrap();
 
-        assert!(result.is_ok());
+        assert_eq!(
+            FrameStats {
+                frames: 3,
+                dropped: 8,
+                elapsed: Duration::from_millis(160),
+            },
+            stats
+        );
+        assert_eq!(
+            Some(Color::from(0x0000FF)),
+            get_colors(&mut backend).unwrap().right
+        );
     }
 }
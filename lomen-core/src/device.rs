@@ -0,0 +1,545 @@
+use crate::backend;
+use crate::backend::Backend;
+use crate::capabilities::Capabilities;
+use crate::color::LightingColors;
+use crate::control;
+use crate::error::LomenError;
+use crate::keyboard::KeyboardType;
+use crate::protocol::{
+    CMD_TYPE_GET_LIGHT_BAR_COLORS, CMD_TYPE_GET_ZONE_COLORS, CMD_TYPE_SET_LIGHT_BAR_COLORS,
+    CMD_TYPE_SET_ZONE_COLORS,
+};
+use crate::response::{LightingStatus, PlatformInfo, ZoneColors};
+use crate::scheduler::{FrameScheduler, FrameStats};
+use crate::transition::{LightingColorsTransition, TransitionOptions};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Colors buffer kept by the session so that writes do not need to read it first
+struct CachedBuffer {
+    get_type: u32,
+    set_type: u32,
+    zone_colors: Option<ZoneColors>,
+}
+
+impl CachedBuffer {
+    fn new(get_type: u32, set_type: u32) -> Self {
+        Self {
+            get_type,
+            set_type,
+            zone_colors: None,
+        }
+    }
+
+    /// Reads buffer from the device refreshing cached copy
+    fn read(&mut self, backend: &mut dyn Backend) -> Result<LightingColors, LomenError> {
+        self.zone_colors = None;
+        let zone_colors = control::read_zone_colors(backend, self.get_type)?;
+        let colors = zone_colors.colors();
+        self.zone_colors = Some(zone_colors);
+        Ok(colors)
+    }
+
+    /// Returns cached colors reading them from the device if there are none
+    fn colors(&mut self, backend: &mut dyn Backend) -> Result<LightingColors, LomenError> {
+        match &self.zone_colors {
+            Some(zone_colors) => Ok(zone_colors.colors()),
+            None => self.read(backend),
+        }
+    }
+
+    /// Writes colors into cached buffer and sends it to the device unless nothing has changed
+    fn write(
+        &mut self,
+        backend: &mut dyn Backend,
+        colors: &LightingColors,
+    ) -> Result<(), LomenError> {
+        let current = match self.zone_colors.take() {
+            Some(zone_colors) => zone_colors,
+            None => control::read_zone_colors(backend, self.get_type)?,
+        };
+
+        let mut zone_colors = current.clone();
+        zone_colors.set_colors(colors);
+
+        if zone_colors != current {
+            control::write_zone_buffer(backend, self.set_type, &zone_colors)?;
+        }
+
+        self.zone_colors = Some(zone_colors);
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.zone_colors = None;
+    }
+}
+
+/// Colors buffer of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Buffer {
+    Zones,
+    LightBar,
+}
+
+/// Colors buffers cached by sessions. Backends are bound to the thread that opened them, but
+/// sessions of several threads can share one cache, so that a write by any of them is seen by
+/// the others and no session writes back stale colors of zones it has not set.
+pub struct SessionCache {
+    zones: CachedBuffer,
+    light_bar: CachedBuffer,
+}
+
+impl SessionCache {
+    pub fn new() -> Self {
+        Self {
+            zones: CachedBuffer::new(CMD_TYPE_GET_ZONE_COLORS, CMD_TYPE_SET_ZONE_COLORS),
+            light_bar: CachedBuffer::new(
+                CMD_TYPE_GET_LIGHT_BAR_COLORS,
+                CMD_TYPE_SET_LIGHT_BAR_COLORS,
+            ),
+        }
+    }
+
+    /// Drops cached buffers so that the next write reads them from the device again
+    pub fn refresh(&mut self) {
+        self.zones.invalidate();
+        self.light_bar.invalidate();
+    }
+
+    fn buffer(&mut self, buffer: Buffer) -> &mut CachedBuffer {
+        match buffer {
+            Buffer::Zones => &mut self.zones,
+            Buffer::LightBar => &mut self.light_bar,
+        }
+    }
+}
+
+impl Default for SessionCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Session cache shared by sessions of several threads
+pub type SharedSessionCache = Arc<Mutex<SessionCache>>;
+
+/// Session with OMEN lighting interface.
+///
+/// Backend is opened once and reused by all operations so that repeated calls (e.g. animation
+/// frames) do not pay for connection setup. Zone and light bar colors buffers are cached, so
+/// writes send only the set command and identical frames are not sent at all. Cached buffer is
+/// dropped on failure or by [`OmenDevice::refresh`].
+pub struct OmenDevice {
+    backend: Box<dyn Backend>,
+    keyboard_type: Option<KeyboardType>,
+    cache: SharedSessionCache,
+}
+
+fn lock(cache: &Mutex<SessionCache>) -> MutexGuard<'_, SessionCache> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Plays transition of `buffer` locking the cache for every frame only, so that sessions
+/// sharing it can write in between
+fn transit(
+    backend: &mut dyn Backend,
+    cache: &Mutex<SessionCache>,
+    buffer: Buffer,
+    to_colors: &LightingColors,
+    options: &TransitionOptions,
+) -> Result<FrameStats, LomenError> {
+    let scheduler = FrameScheduler::new(options.duration, options.fps)?;
+    let from_colors = lock(cache).buffer(buffer).colors(backend)?;
+    let transition = LightingColorsTransition::new(from_colors, *to_colors).with_options(options);
+
+    scheduler.run(|progress| {
+        lock(cache)
+            .buffer(buffer)
+            .write(backend, &transition.sample(progress))
+    })
+}
+
+impl OmenDevice {
+    /// Opens session with backend native for the current platform
+    pub fn open() -> Result<Self, LomenError> {
+        Ok(Self::with_backend(backend::default_backend()?))
+    }
+
+    pub fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Self::with_shared_cache(backend, SharedSessionCache::default())
+    }
+
+    /// Creates session keeping colors buffers in `cache` shared with other sessions
+    pub fn with_shared_cache(backend: Box<dyn Backend>, cache: SharedSessionCache) -> Self {
+        Self {
+            backend,
+            keyboard_type: None,
+            cache,
+        }
+    }
+
+    /// Drops cached colors buffers so that the next write reads them from the device again.
+    /// Call it when colors might have been changed by someone else.
+    pub fn refresh(&mut self) {
+        lock(&self.cache).refresh();
+    }
+
+    pub fn backend(&mut self) -> &mut dyn Backend {
+        self.backend.as_mut()
+    }
+
+    /// Returns keyboard type. It is queried once per session.
+    pub fn get_keyboard_type(&mut self) -> Result<KeyboardType, LomenError> {
+        if let Some(keyboard_type) = self.keyboard_type {
+            return Ok(keyboard_type);
+        }
+
+        let keyboard_type = control::get_keyboard_type(self.backend())?;
+        self.keyboard_type = Some(keyboard_type);
+        Ok(keyboard_type)
+    }
+
+    pub fn get_platform_info(&mut self) -> Result<PlatformInfo, LomenError> {
+        control::get_platform_info(self.backend())
+    }
+
+    pub fn is_lighting_supported(&mut self) -> Result<bool, LomenError> {
+        control::is_lighting_supported(self.backend())
+    }
+
+    pub fn is_light_bar_supported(&mut self) -> Result<bool, LomenError> {
+        control::is_light_bar_supported(self.backend())
+    }
+
+    pub fn get_capabilities(&mut self) -> Result<Capabilities, LomenError> {
+        control::get_capabilities(self.backend())
+    }
+
+    /// Checks that keyboard zones can be driven by zone colors commands
+    pub fn check_zone_lighting(&mut self) -> Result<(), LomenError> {
+        self.get_keyboard_type()?.check_zone_lighting()
+    }
+
+    /// Returns keyboard lighting colors read from the device
+    pub fn get_colors(&mut self) -> Result<LightingColors, LomenError> {
+        lock(&self.cache).zones.read(self.backend.as_mut())
+    }
+
+    /// Sets keyboard lighting colors. Unset zones keep their current colors.
+    pub fn set_colors(&mut self, colors: &LightingColors) -> Result<(), LomenError> {
+        lock(&self.cache).zones.write(self.backend.as_mut(), colors)
+    }
+
+    /// Smoothly changes keyboard lighting colors starting from the cached ones. Unset zones keep
+    /// their current colors as with [`OmenDevice::set_colors`].
+    pub fn transit_colors(
+        &mut self,
+        to_colors: &LightingColors,
+        options: &TransitionOptions,
+    ) -> Result<FrameStats, LomenError> {
+        transit(
+            self.backend.as_mut(),
+            &self.cache,
+            Buffer::Zones,
+            to_colors,
+            options,
+        )
+    }
+
+    /// Returns light bar colors read from the device
+    pub fn get_light_bar_colors(&mut self) -> Result<LightingColors, LomenError> {
+        lock(&self.cache).light_bar.read(self.backend.as_mut())
+    }
+
+    pub fn set_light_bar_colors(&mut self, colors: &LightingColors) -> Result<(), LomenError> {
+        lock(&self.cache)
+            .light_bar
+            .write(self.backend.as_mut(), colors)
+    }
+
+    pub fn transit_light_bar_colors(
+        &mut self,
+        to_colors: &LightingColors,
+        options: &TransitionOptions,
+    ) -> Result<FrameStats, LomenError> {
+        transit(
+            self.backend.as_mut(),
+            &self.cache,
+            Buffer::LightBar,
+            to_colors,
+            options,
+        )
+    }
+
+    pub fn get_status(&mut self) -> Result<LightingStatus, LomenError> {
+        control::get_status(self.backend())
+    }
+
+    pub fn get_brightness(&mut self) -> Result<u8, LomenError> {
+        control::get_brightness(self.backend())
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<(), LomenError> {
+        control::set_brightness(self.backend(), brightness)
+    }
+
+    pub fn set_lighting_enabled(&mut self, enabled: bool) -> Result<(), LomenError> {
+        control::set_lighting_enabled(self.backend(), enabled)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::EmulatorBackend;
+    use crate::color::Color;
+    use crate::error::ReturnCode;
+    use crate::protocol::{BUFFER_SIZE, BiosRequest, BiosResponse, CMD_COMMON};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Emulator shared with the test to inspect it while the device owns the backend
+    #[derive(Clone, Default)]
+    struct SharedEmulator(Rc<RefCell<EmulatorBackend>>);
+
+    impl Backend for SharedEmulator {
+        fn execute(&mut self, request: &BiosRequest) -> Result<BiosResponse, LomenError> {
+            self.0.borrow_mut().execute(request)
+        }
+    }
+
+    fn shared_device() -> (OmenDevice, SharedEmulator) {
+        let emulator = SharedEmulator::default();
+        (
+            OmenDevice::with_backend(Box::new(emulator.clone())),
+            emulator,
+        )
+    }
+
+    fn colors(color: u64) -> LightingColors {
+        LightingColors {
+            right: Some(Color::from(color)),
+            center: None,
+            left: None,
+            game: None,
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let mut device = OmenDevice::with_backend(Box::new(EmulatorBackend::new()));
+        let colors = LightingColors {
+            right: Some(Color::from(0x102030)),
+            center: Some(Color::from(0x405060)),
+            left: Some(Color::from(0x708090)),
+            game: Some(Color::from(0xA0B0C0)),
+        };
+
+        device.set_colors(&colors).unwrap();
+        device.set_brightness(50).unwrap();
+
+        assert_eq!(colors, device.get_colors().unwrap());
+        assert_eq!(50, device.get_brightness().unwrap());
+    }
+
+    #[test]
+    fn test_check_zone_lighting() {
+        let mut emulator = EmulatorBackend::new();
+        emulator.set_keyboard_type(3);
+        let mut device = OmenDevice::with_backend(Box::new(emulator));
+
+        assert_eq!(KeyboardType::PerKeyRgb, device.get_keyboard_type().unwrap());
+        assert!(device.check_zone_lighting().is_err());
+        assert_eq!(KeyboardType::PerKeyRgb, device.get_keyboard_type().unwrap());
+    }
+
+    #[test]
+    fn test_writes_skip_read() {
+        let (mut device, emulator) = shared_device();
+
+        device.set_colors(&colors(0x010101)).unwrap();
+        device.set_colors(&colors(0x020202)).unwrap();
+        device.set_colors(&colors(0x030303)).unwrap();
+
+        assert_eq!(
+            &[
+                (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS),
+                (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS),
+                (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS),
+                (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS),
+            ],
+            emulator.0.borrow().history()
+        );
+        assert_eq!(0x03, emulator.0.borrow().zone_buffer()[25]);
+    }
+
+    #[test]
+    fn test_identical_frames_are_skipped() {
+        let (mut device, emulator) = shared_device();
+
+        device.set_colors(&colors(0x010101)).unwrap();
+        device.set_colors(&colors(0x010101)).unwrap();
+        device.set_colors(&LightingColors::default()).unwrap();
+
+        let history = emulator.0.borrow().history().to_vec();
+        assert_eq!(
+            1,
+            history
+                .iter()
+                .filter(|&&entry| entry == (CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS))
+                .count()
+        );
+    }
+
+    #[test]
+    fn test_cache_invalidation() {
+        let (mut device, emulator) = shared_device();
+        device.set_colors(&colors(0x010101)).unwrap();
+
+        /* failed write drops the cache */
+        emulator
+            .0
+            .borrow_mut()
+            .set_return_code(CMD_TYPE_SET_ZONE_COLORS, 5);
+        assert!(matches!(
+            device.set_colors(&colors(0x020202)),
+            Err(LomenError::ReturnCode(ReturnCode::InvalidParameters))
+        ));
+        emulator
+            .0
+            .borrow_mut()
+            .set_return_code(CMD_TYPE_SET_ZONE_COLORS, 0);
+
+        emulator.0.borrow_mut().clear_history();
+        device.set_colors(&colors(0x030303)).unwrap();
+        assert_eq!(
+            (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS),
+            emulator.0.borrow().history()[0]
+        );
+
+        /* explicit refresh picks up changes made by someone else */
+        let mut buffer = *emulator.0.borrow().zone_buffer();
+        buffer[28] = 0x44;
+        emulator.0.borrow_mut().set_zone_buffer(buffer);
+        device.refresh();
+        device.set_colors(&colors(0x050505)).unwrap();
+
+        assert_eq!(0x44, emulator.0.borrow().zone_buffer()[28]);
+        assert_eq!(0x05, emulator.0.borrow().zone_buffer()[25]);
+    }
+
+    #[test]
+    fn test_transit_colors() {
+        let (mut device, emulator) = shared_device();
+        let target = LightingColors {
+            right: Some(Color::from(0xFF0000)),
+            center: Some(Color::from(0x00FF00)),
+            left: Some(Color::from(0x0000FF)),
+            game: Some(Color::from(0xFFFFFF)),
+        };
+
+        device
+            .transit_colors(
+                &target,
+                &TransitionOptions::new(Duration::from_millis(50), 50),
+            )
+            .unwrap();
+
+        assert_eq!(
+            1,
+            emulator
+                .0
+                .borrow()
+                .history()
+                .iter()
+                .filter(|&&entry| entry == (CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS))
+                .count()
+        );
+        assert_eq!(target, device.get_colors().unwrap());
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let emulator = SharedEmulator::default();
+        let cache = SharedSessionCache::default();
+        let mut first = OmenDevice::with_shared_cache(Box::new(emulator.clone()), cache.clone());
+        let mut second = OmenDevice::with_shared_cache(Box::new(emulator.clone()), cache);
+
+        second.get_colors().unwrap();
+        first
+            .set_colors(&LightingColors {
+                center: Some(Color::from(0x00FF00)),
+                ..colors(0xFF0000)
+            })
+            .unwrap();
+        // Partial write of the other session keeps the zone set by the first one
+        second.set_colors(&colors(0x0000FF)).unwrap();
+
+        let mut fresh = OmenDevice::with_backend(Box::new(emulator));
+        let result = fresh.get_colors().unwrap();
+        assert_eq!(Some(Color::from(0x0000FF)), result.right);
+        assert_eq!(Some(Color::from(0x00FF00)), result.center);
+    }
+
+    #[test]
+    fn test_set_and_transit_keep_unset_zones() {
+        let target = LightingColors {
+            right: Some(Color::from(0x0000FF)),
+            center: None,
+            left: Some(Color::from(0x00FF00)),
+            game: None,
+        };
+
+        let mut buffer = [0; BUFFER_SIZE];
+        buffer[25..37].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        let (mut set_device, set_emulator) = shared_device();
+        set_emulator.0.borrow_mut().set_zone_buffer(buffer);
+        set_device.set_colors(&target).unwrap();
+
+        let (mut transit_device, transit_emulator) = shared_device();
+        transit_emulator.0.borrow_mut().set_zone_buffer(buffer);
+        let initial = transit_device.get_colors().unwrap();
+        transit_device
+            .transit_colors(
+                &target,
+                &TransitionOptions::new(Duration::from_millis(50), 50),
+            )
+            .unwrap();
+
+        let colors = transit_device.get_colors().unwrap();
+        assert_eq!(set_device.get_colors().unwrap(), colors);
+        assert_eq!(initial.center, colors.center);
+        assert_eq!(initial.game, colors.game);
+    }
+
+    #[test]
+    fn test_write_after_oversized_response() {
+        let (mut device, emulator) = shared_device();
+        emulator
+            .0
+            .borrow_mut()
+            .set_response_size(CMD_TYPE_GET_ZONE_COLORS, 256);
+
+        device.set_colors(&colors(0xFF0000)).unwrap();
+        device
+            .set_colors(&LightingColors {
+                right: None,
+                game: Some(Color::from(0x00FF00)),
+                ..colors(0)
+            })
+            .unwrap();
+        device
+            .transit_colors(
+                &colors(0x0000FF),
+                &TransitionOptions::new(Duration::from_millis(20), 50),
+            )
+            .unwrap();
+
+        device.refresh();
+        let colors = device.get_colors().unwrap();
+        assert_eq!(Some(Color::from(0x0000FF)), colors.right);
+        assert_eq!(Some(Color::from(0x00FF00)), colors.game);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::LomenError;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Timing function mapping linear progress of transition to eased one
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// CSS-like cubic Bézier curve through `(0, 0)`, `(x1, y1)`, `(x2, y2)` and `(1, 1)`
+    CubicBezier(f32, f32, f32, f32),
+    /// Jumps in given number of equal steps at the end of every interval. `Steps(0)` behaves as
+    /// `Steps(1)`.
+    Steps(u32),
+}
+
+impl Easing {
+    /// Returns eased progress for linear progress `t` in range `0.0..=1.0`
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => cubic_bezier(0.42, 0.0, 1.0, 1.0, t),
+            Easing::EaseOut => cubic_bezier(0.0, 0.0, 0.58, 1.0, t),
+            Easing::EaseInOut => cubic_bezier(0.42, 0.0, 0.58, 1.0, t),
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+            Easing::Steps(steps) => {
+                let steps = steps.max(1) as f32;
+                (t * steps).floor() / steps
+            }
+        }
+    }
+}
+
+/// Returns coordinate of 1D cubic Bézier curve with ends at 0 and 1
+fn bezier(p1: f32, p2: f32, s: f32) -> f32 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s
+}
+
+fn bezier_derivative(p1: f32, p2: f32, s: f32) -> f32 {
+    let r = 1.0 - s;
+    3.0 * r * r * p1 + 6.0 * r * s * (p2 - p1) + 3.0 * s * s * (1.0 - p2)
+}
+
+/// Finds `y` of the curve at `x` solving for curve parameter with Newton's method and falling
+/// back to bisection
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, x: f32) -> f32 {
+    let mut s = x;
+    for _ in 0..8 {
+        let error = bezier(x1, x2, s) - x;
+        if error.abs() < 1e-6 {
+            return bezier(y1, y2, s);
+        }
+        let derivative = bezier_derivative(x1, x2, s);
+        if derivative.abs() < 1e-6 {
+            break;
+        }
+        s -= error / derivative;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+    s = x;
+    for _ in 0..32 {
+        let value = bezier(x1, x2, s);
+        if (value - x).abs() < 1e-6 {
+            break;
+        }
+        if value < x {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+
+    bezier(y1, y2, s)
+}
+
+fn invalid(s: &str) -> LomenError {
+    LomenError::InvalidArgument(format!(
+        "unknown easing `{}`, expected linear, ease-in, ease-out, ease-in-out, \
+         cubic-bezier(x1,y1,x2,y2) or steps(n)",
+        s
+    ))
+}
+
+/// Parses arguments of function-like notation, e.g. `steps(4)`
+fn parse_args<'a>(s: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let args = s
+        .strip_prefix(name)?
+        .trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    Some(args.split(',').map(str::trim).collect())
+}
+
+impl FromStr for Easing {
+    type Err = LomenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "linear" => return Ok(Easing::Linear),
+            "ease-in" => return Ok(Easing::EaseIn),
+            "ease-out" => return Ok(Easing::EaseOut),
+            "ease-in-out" => return Ok(Easing::EaseInOut),
+            _ => {}
+        }
+
+        if let Some(args) = parse_args(&value, "steps") {
+            return match args.as_slice() {
+                [n] => match n.parse::<u32>() {
+                    Ok(steps) if steps > 0 => Ok(Easing::Steps(steps)),
+                    _ => Err(invalid(s)),
+                },
+                _ => Err(invalid(s)),
+            };
+        }
+
+        if let Some(args) = parse_args(&value, "cubic-bezier") {
+            let points: Vec<f32> = args
+                .iter()
+                .map(|a| a.parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid(s))?;
+
+            return match points.as_slice() {
+                &[x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => {
+                    Ok(Easing::CubicBezier(x1, y1, x2, y2))
+                }
+                _ => Err(invalid(s)),
+            };
+        }
+
+        Err(invalid(s))
+    }
+}
+
+impl Display for Easing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Easing::Linear => write!(f, "linear"),
+            Easing::EaseIn => write!(f, "ease-in"),
+            Easing::EaseOut => write!(f, "ease-out"),
+            Easing::EaseInOut => write!(f, "ease-in-out"),
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                write!(f, "cubic-bezier({}, {}, {}, {})", x1, y1, x2, y2)
+            }
+            Easing::Steps(steps) => write!(f, "steps({})", steps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(expected: f32, actual: f32) {
+        assert!(
+            (expected - actual).abs() < 1e-3,
+            "expected {}, but {} found",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_ends() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::CubicBezier(0.1, 0.7, 0.9, 0.2),
+            Easing::Steps(3),
+        ] {
+            assert_close(0.0, easing.apply(0.0));
+            assert_close(1.0, easing.apply(1.0));
+            assert_close(1.0, easing.apply(2.0));
+        }
+    }
+
+    #[test]
+    fn test_curves() {
+        assert_close(0.25, Easing::Linear.apply(0.25));
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert_close(0.5, Easing::EaseInOut.apply(0.5));
+        assert_close(0.3, Easing::CubicBezier(0.0, 0.0, 1.0, 1.0).apply(0.3));
+        assert_close(
+            0.3,
+            Easing::CubicBezier(1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0).apply(0.3),
+        );
+    }
+
+    #[test]
+    fn test_steps() {
+        let easing = Easing::Steps(4);
+
+        assert_eq!(0.0, easing.apply(0.2));
+        assert_eq!(0.25, easing.apply(0.25));
+        assert_eq!(0.75, easing.apply(0.99));
+
+        // Zero steps jump once at the end instead of producing NaN
+        let easing = Easing::Steps(0);
+        assert_eq!(0.0, easing.apply(0.5));
+        assert_eq!(1.0, easing.apply(1.0));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Easing::EaseInOut, "Ease-In-Out".parse().unwrap());
+        assert_eq!(Easing::Steps(5), "steps(5)".parse().unwrap());
+        assert_eq!(
+            Easing::CubicBezier(0.25, 0.1, 0.25, 1.0),
+            "cubic-bezier(0.25, 0.1, 0.25, 1)".parse().unwrap()
+        );
+
+        for s in [
+            "bounce",
+            "steps(0)",
+            "steps(a)",
+            "cubic-bezier(2, 0, 1, 1)",
+            "cubic-bezier(1)",
+        ] {
+            assert!(matches!(
+                s.parse::<Easing>(),
+                Err(LomenError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseOut,
+            Easing::CubicBezier(0.1, 0.2, 0.3, 0.4),
+            Easing::Steps(7),
+        ] {
+            assert_eq!(easing, easing.to_string().parse().unwrap());
+        }
+    }
+}
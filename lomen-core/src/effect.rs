@@ -0,0 +1,507 @@
+use crate::color::{Color, LightingColors};
+use crate::color_space::{ColorSpace, from_hsl};
+use crate::error::LomenError;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Lighting animation computed frame by frame
+pub trait Effect: Send {
+    /// Returns colors at time `t` since the effect was started
+    fn frame(&mut self, t: Duration) -> LightingColors;
+}
+
+/// Order in which effects move across zones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    /// From right zone to game zone
+    #[default]
+    Forward,
+    /// From game zone to right zone
+    Backward,
+}
+
+impl Direction {
+    /// Returns position of zone along the direction, zones are indexed from right to game
+    fn position(&self, zone_index: usize) -> usize {
+        match self {
+            Direction::Forward => zone_index,
+            Direction::Backward => ZONE_COUNT - 1 - zone_index,
+        }
+    }
+}
+
+/// Parameters shared by built-in effects
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectParams {
+    /// Colors used by the effect, missing ones take effect defaults
+    pub colors: Vec<Color>,
+    /// Number of effect cycles per second
+    pub speed: f32,
+    pub direction: Direction,
+}
+
+impl Default for EffectParams {
+    fn default() -> Self {
+        Self {
+            colors: Vec::new(),
+            speed: 0.5,
+            direction: Direction::Forward,
+        }
+    }
+}
+
+impl EffectParams {
+    fn color(&self, index: usize, default: Color) -> Color {
+        self.colors.get(index).copied().unwrap_or(default)
+    }
+
+    /// Returns duration of one effect cycle, speed must give a non-zero representable period
+    fn period(&self) -> Result<Duration, LomenError> {
+        if !self.speed.is_finite() || self.speed <= 0.0 {
+            return Err(LomenError::InvalidArgument(format!(
+                "effect speed must be positive, but {} given",
+                self.speed
+            )));
+        }
+
+        match Duration::try_from_secs_f32(1.0 / self.speed) {
+            Ok(period) if !period.is_zero() => Ok(period),
+            _ => Err(LomenError::InvalidArgument(format!(
+                "effect speed {} is out of range",
+                self.speed
+            ))),
+        }
+    }
+}
+
+/// Built-in effects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectKind {
+    Breathing,
+    ColorCycle,
+    Rainbow,
+    Wave,
+    Strobe,
+    Candle,
+    Gradient,
+}
+
+impl EffectKind {
+    /// Creates effect with given parameters
+    pub fn create(&self, params: &EffectParams) -> Result<Box<dyn Effect>, LomenError> {
+        let period = params.period()?;
+        let direction = params.direction;
+        Ok(match self {
+            EffectKind::Breathing => Box::new(Breathing {
+                color: params.color(0, WHITE),
+                period,
+            }),
+            EffectKind::ColorCycle => Box::new(Rainbow {
+                period,
+                spread: 0.0,
+                direction,
+            }),
+            EffectKind::Rainbow => Box::new(Rainbow {
+                period,
+                spread: 360.0 / ZONE_COUNT as f32,
+                direction,
+            }),
+            EffectKind::Wave => Box::new(Wave {
+                color: params.color(0, WHITE),
+                background: params.color(1, BLACK),
+                period,
+                direction,
+            }),
+            EffectKind::Strobe => Box::new(Strobe {
+                color: params.color(0, WHITE),
+                background: params.color(1, BLACK),
+                period,
+            }),
+            EffectKind::Candle => Box::new(Candle {
+                color: params.color(0, CANDLE),
+                period,
+            }),
+            EffectKind::Gradient => Box::new(Gradient {
+                colors: match params.colors.len() {
+                    0 => vec![RED, BLUE],
+                    1 => vec![params.colors[0], BLACK],
+                    _ => params.colors.clone(),
+                },
+                direction,
+            }),
+        })
+    }
+}
+
+impl FromStr for EffectKind {
+    type Err = LomenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "breathing" => Ok(EffectKind::Breathing),
+            "cycle" | "color-cycle" => Ok(EffectKind::ColorCycle),
+            "rainbow" => Ok(EffectKind::Rainbow),
+            "wave" => Ok(EffectKind::Wave),
+            "strobe" | "flash" => Ok(EffectKind::Strobe),
+            "candle" => Ok(EffectKind::Candle),
+            "gradient" => Ok(EffectKind::Gradient),
+            _ => Err(LomenError::InvalidArgument(format!(
+                "unknown effect `{}`, expected breathing, cycle, rainbow, wave, strobe, candle \
+                 or gradient",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for EffectKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectKind::Breathing => write!(f, "breathing"),
+            EffectKind::ColorCycle => write!(f, "cycle"),
+            EffectKind::Rainbow => write!(f, "rainbow"),
+            EffectKind::Wave => write!(f, "wave"),
+            EffectKind::Strobe => write!(f, "strobe"),
+            EffectKind::Candle => write!(f, "candle"),
+            EffectKind::Gradient => write!(f, "gradient"),
+        }
+    }
+}
+
+const ZONE_COUNT: usize = 4;
+
+/* Default colors */
+const BLACK: Color = Color::new(0, 0, 0);
+const WHITE: Color = Color::new(0xFF, 0xFF, 0xFF);
+const RED: Color = Color::new(0xFF, 0, 0);
+const BLUE: Color = Color::new(0, 0, 0xFF);
+const CANDLE: Color = Color::new(0xFF, 0x80, 0x10);
+
+/// Returns fraction of the current cycle in range `0.0..1.0`. Cycles are counted in integers,
+/// so the phase stays precise however long the effect runs.
+fn phase(t: Duration, period: Duration) -> f32 {
+    fraction(t.as_nanos(), period.as_nanos())
+}
+
+fn fraction(nanos: u128, period: u128) -> f32 {
+    ((nanos % period) as f64 / period as f64) as f32
+}
+
+fn scale(color: Color, factor: f32) -> Color {
+    let scale = |c: u8| -> u8 { (c as f32 * factor.clamp(0.0, 1.0)).round() as u8 };
+    Color::new(scale(color.r), scale(color.g), scale(color.b))
+}
+
+fn zones(color: impl Fn(usize) -> Color) -> LightingColors {
+    LightingColors {
+        right: Some(color(0)),
+        center: Some(color(1)),
+        left: Some(color(2)),
+        game: Some(color(3)),
+    }
+}
+
+/// Color fading in and out
+pub struct Breathing {
+    pub color: Color,
+    pub period: Duration,
+}
+
+impl Effect for Breathing {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        let level = (1.0 - (2.0 * PI * phase(t, self.period)).cos()) / 2.0;
+        zones(|_| scale(self.color, level))
+    }
+}
+
+/// Hue cycling through the color wheel. Zones are shifted by `spread` degrees, zero spread
+/// makes all zones the same color.
+pub struct Rainbow {
+    pub period: Duration,
+    pub spread: f32,
+    pub direction: Direction,
+}
+
+impl Effect for Rainbow {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        let hue = 360.0 * phase(t, self.period);
+        zones(|zone| {
+            let hue = hue - self.spread * self.direction.position(zone) as f32;
+            ColorSpace::Hsl.quantize(from_hsl([hue, 1.0, 0.5]))
+        })
+    }
+}
+
+/// Pulse of color moving across zones over background color
+pub struct Wave {
+    pub color: Color,
+    pub background: Color,
+    pub period: Duration,
+    pub direction: Direction,
+}
+
+impl Effect for Wave {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        let head = phase(t, self.period) * ZONE_COUNT as f32;
+        zones(|zone| {
+            let position = self.direction.position(zone) as f32;
+            let distance = (head - position).rem_euclid(ZONE_COUNT as f32);
+            let distance = distance.min(ZONE_COUNT as f32 - distance);
+            let level = (1.0 - distance).max(0.0);
+            ColorSpace::Srgb.interpolate(self.background, self.color, level)
+        })
+    }
+}
+
+/// Color flashing on for the first half of every period
+pub struct Strobe {
+    pub color: Color,
+    pub background: Color,
+    pub period: Duration,
+}
+
+impl Effect for Strobe {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        let color = if phase(t, self.period) < 0.5 {
+            self.color
+        } else {
+            self.background
+        };
+        zones(|_| color)
+    }
+}
+
+/// Flickering color with every zone changing brightness independently
+pub struct Candle {
+    pub color: Color,
+    /// Time between random brightness targets
+    pub period: Duration,
+}
+
+/// Returns pseudo-random value in range `0.0..1.0` for given zone and step
+fn noise(zone: usize, step: u64) -> f32 {
+    let mut x = step
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .wrapping_add((zone as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9));
+    x ^= x >> 31;
+    x = x.wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 29;
+    (x >> 40) as f32 / (1u64 << 24) as f32
+}
+
+impl Effect for Candle {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        // A few random brightness targets per period, smoothly blended
+        let position = t.as_nanos() * 8;
+        let period = self.period.as_nanos();
+        let step = (position / period) as u64;
+        let f = fraction(position, period);
+        let f = f * f * (3.0 - 2.0 * f);
+
+        zones(|zone| {
+            let level = noise(zone, step) * (1.0 - f) + noise(zone, step.wrapping_add(1)) * f;
+            scale(self.color, 0.55 + 0.45 * level)
+        })
+    }
+}
+
+/// Static gradient spread over zones
+pub struct Gradient {
+    /// Gradient stops, at least two
+    pub colors: Vec<Color>,
+    pub direction: Direction,
+}
+
+impl Effect for Gradient {
+    fn frame(&mut self, _t: Duration) -> LightingColors {
+        if let [] | [_] = self.colors.as_slice() {
+            let color = self.colors.first().copied().unwrap_or(BLACK);
+            return zones(|_| color);
+        }
+
+        let segments = (self.colors.len() - 1) as f32;
+        zones(|zone| {
+            let position = self.direction.position(zone) as f32 / (ZONE_COUNT - 1) as f32;
+            let position = position * segments;
+            let index = (position as usize).min(self.colors.len() - 2);
+            ColorSpace::Oklab.interpolate(
+                self.colors[index],
+                self.colors[index + 1],
+                position - index as f32,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL: [EffectKind; 7] = [
+        EffectKind::Breathing,
+        EffectKind::ColorCycle,
+        EffectKind::Rainbow,
+        EffectKind::Wave,
+        EffectKind::Strobe,
+        EffectKind::Candle,
+        EffectKind::Gradient,
+    ];
+
+    fn effect(kind: EffectKind, colors: &[u64], direction: Direction) -> Box<dyn Effect> {
+        kind.create(&EffectParams {
+            colors: colors.iter().map(|&c| Color::from(c)).collect(),
+            speed: 1.0,
+            direction,
+        })
+        .unwrap()
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_all_zones_are_set() {
+        for kind in ALL {
+            let colors = effect(kind, &[], Direction::Forward).frame(ms(300));
+            assert!(colors.right.is_some(), "{}", kind);
+            assert!(colors.center.is_some(), "{}", kind);
+            assert!(colors.left.is_some(), "{}", kind);
+            assert!(colors.game.is_some(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_breathing() {
+        let mut effect = effect(EffectKind::Breathing, &[0xFF8000], Direction::Forward);
+
+        assert_eq!(Some(Color::from(0x000000)), effect.frame(ms(0)).right);
+        assert_eq!(Some(Color::from(0xFF8000)), effect.frame(ms(500)).right);
+        assert_eq!(Some(Color::from(0x804000)), effect.frame(ms(1250)).game);
+    }
+
+    #[test]
+    fn test_rainbow() {
+        let mut cycle = effect(EffectKind::ColorCycle, &[], Direction::Forward);
+        let colors = cycle.frame(ms(0));
+        assert_eq!(Some(Color::from(0xFF0000)), colors.right);
+        assert_eq!(colors.right, colors.game);
+        assert_eq!(Some(Color::from(0x00FFFF)), cycle.frame(ms(500)).left);
+
+        let mut rainbow = effect(EffectKind::Rainbow, &[], Direction::Forward);
+        let colors = rainbow.frame(ms(500));
+        assert_eq!(Some(Color::from(0x00FFFF)), colors.right);
+        assert_eq!(Some(Color::from(0x80FF00)), colors.center);
+        assert_eq!(Some(Color::from(0xFF0000)), colors.left);
+    }
+
+    #[test]
+    fn test_wave_direction() {
+        let mut forward = effect(EffectKind::Wave, &[0xFFFFFF, 0x000000], Direction::Forward);
+        let mut backward = effect(EffectKind::Wave, &[0xFFFFFF, 0x000000], Direction::Backward);
+
+        // The head is at the center zone after a quarter of the period
+        let colors = forward.frame(ms(250));
+        assert_eq!(Some(Color::from(0xFFFFFF)), colors.center);
+        assert_eq!(Some(Color::from(0x000000)), colors.game);
+
+        let colors = backward.frame(ms(250));
+        assert_eq!(Some(Color::from(0xFFFFFF)), colors.left);
+        assert_eq!(Some(Color::from(0x000000)), colors.right);
+    }
+
+    #[test]
+    fn test_strobe() {
+        let mut effect = effect(EffectKind::Strobe, &[0x00FF00], Direction::Forward);
+
+        assert_eq!(Some(Color::from(0x00FF00)), effect.frame(ms(100)).left);
+        assert_eq!(Some(Color::from(0x000000)), effect.frame(ms(700)).left);
+        assert_eq!(Some(Color::from(0x00FF00)), effect.frame(ms(1100)).left);
+    }
+
+    #[test]
+    fn test_candle() {
+        let mut effect = effect(EffectKind::Candle, &[0xFF0000], Direction::Forward);
+        let frames: Vec<LightingColors> = (0..100).map(|i| effect.frame(ms(i * 10))).collect();
+
+        assert!(frames.iter().all(|c| c.right.unwrap().r >= 140));
+        assert!(frames.windows(2).any(|w| w[0] != w[1]));
+        assert_eq!(frames[42], effect.frame(ms(420)));
+    }
+
+    #[test]
+    fn test_long_running() {
+        // Whole cycles later the effects look the same
+        let days = ms(30 * 24 * 3600 * 1000);
+        for kind in [EffectKind::Breathing, EffectKind::Rainbow, EffectKind::Wave] {
+            let mut effect = effect(kind, &[], Direction::Forward);
+            for i in 0..10 {
+                assert_eq!(effect.frame(ms(i * 100)), effect.frame(days + ms(i * 100)));
+            }
+        }
+
+        let mut candle = effect(EffectKind::Candle, &[0xFF0000], Direction::Forward);
+        let frames: Vec<LightingColors> =
+            (0..10).map(|i| candle.frame(days + ms(i * 10))).collect();
+        assert!(frames.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_gradient() {
+        let mut effect = effect(
+            EffectKind::Gradient,
+            &[0xFF0000, 0x0000FF],
+            Direction::Backward,
+        );
+
+        let colors = effect.frame(ms(0));
+        assert_eq!(Some(Color::from(0x0000FF)), colors.right);
+        assert_eq!(Some(Color::from(0xFF0000)), colors.game);
+        assert_eq!(colors, effect.frame(ms(5000)));
+    }
+
+    #[test]
+    fn test_parse() {
+        for kind in ALL {
+            assert_eq!(kind, kind.to_string().parse().unwrap());
+        }
+        assert!(matches!(
+            "disco".parse::<EffectKind>(),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_speed() {
+        let params = EffectParams {
+            speed: 0.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            EffectKind::Wave.create(&params),
+            Err(LomenError::InvalidArgument(_))
+        ));
+
+        // Periods not representable by duration
+        for speed in [1e-39, f32::MAX] {
+            let params = EffectParams {
+                speed,
+                ..Default::default()
+            };
+            assert!(matches!(
+                EffectKind::Wave.create(&params),
+                Err(LomenError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_candle_at_end_of_time() {
+        let mut effect = EffectKind::Candle.create(&EffectParams::default()).unwrap();
+
+        assert!(effect.frame(Duration::MAX).right.is_some());
+    }
+}
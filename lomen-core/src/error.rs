@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Decoded `rwReturnCode` of HP BIOS interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnCode {
+    NotSupported,
+    WrongSignature,
+    UnknownCommand,
+    UnknownCommandType,
+    InvalidParameters,
+    AccessDenied,
+    Other(u32),
+}
+
+impl ReturnCode {
+    /// Returns raw BIOS code
+    pub fn code(&self) -> u32 {
+        match self {
+            ReturnCode::NotSupported => 0x01,
+            ReturnCode::WrongSignature => 0x02,
+            ReturnCode::UnknownCommand => 0x03,
+            ReturnCode::UnknownCommandType => 0x04,
+            ReturnCode::InvalidParameters => 0x05,
+            ReturnCode::AccessDenied => 0x06,
+            ReturnCode::Other(code) => *code,
+        }
+    }
+
+    /// Checks whether the code means the model lacks requested feature
+    pub fn is_not_supported(&self) -> bool {
+        matches!(
+            self,
+            ReturnCode::NotSupported | ReturnCode::UnknownCommand | ReturnCode::UnknownCommandType
+        )
+    }
+}
+
+impl From<u32> for ReturnCode {
+    fn from(value: u32) -> Self {
+        match value {
+            0x01 => ReturnCode::NotSupported,
+            0x02 => ReturnCode::WrongSignature,
+            0x03 => ReturnCode::UnknownCommand,
+            0x04 => ReturnCode::UnknownCommandType,
+            0x05 => ReturnCode::InvalidParameters,
+            0x06 => ReturnCode::AccessDenied,
+            other => ReturnCode::Other(other),
+        }
+    }
+}
+
+impl Display for ReturnCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReturnCode::NotSupported => write!(f, "feature is not supported by this model"),
+            ReturnCode::WrongSignature => write!(f, "invalid request signature"),
+            ReturnCode::UnknownCommand => {
+                write!(f, "unknown command, feature is not supported by this model")
+            }
+            ReturnCode::UnknownCommandType => {
+                write!(
+                    f,
+                    "unknown command type, feature is not supported by this model"
+                )
+            }
+            ReturnCode::InvalidParameters => write!(f, "invalid parameters"),
+            ReturnCode::AccessDenied => write!(f, "access denied, run as administrator"),
+            ReturnCode::Other(code) => write!(f, "unknown error code {}", code),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LomenError {
+    /// Connection to the BIOS interface could not be established
+    Connection(String),
+    /// Required WMI class or method is missing, usually meaning this is not an OMEN machine
+    ClassNotFound(String),
+    /// BIOS rejected the command with non-zero `rwReturnCode`
+    ReturnCode(ReturnCode),
+    /// Response payload does not have expected shape
+    InvalidResponse(String),
+    /// Feature is not supported by the device or backend
+    Unsupported(String),
+    /// Invalid value passed by the caller
+    InvalidArgument(String),
+    /// Transport specific failure
+    Backend(String),
+    /// I/O failure
+    Io(io::Error),
+    /// Animation was cancelled or superseded by another one
+    Cancelled,
+}
+
+impl Clone for LomenError {
+    fn clone(&self) -> Self {
+        match self {
+            LomenError::Connection(s) => LomenError::Connection(s.clone()),
+            LomenError::ClassNotFound(s) => LomenError::ClassNotFound(s.clone()),
+            LomenError::ReturnCode(code) => LomenError::ReturnCode(*code),
+            LomenError::InvalidResponse(s) => LomenError::InvalidResponse(s.clone()),
+            LomenError::Unsupported(s) => LomenError::Unsupported(s.clone()),
+            LomenError::InvalidArgument(s) => LomenError::InvalidArgument(s.clone()),
+            LomenError::Backend(s) => LomenError::Backend(s.clone()),
+            // io::Error is not cloneable, keep its kind and message
+            LomenError::Io(e) => LomenError::Io(io::Error::new(e.kind(), e.to_string())),
+            LomenError::Cancelled => LomenError::Cancelled,
+        }
+    }
+}
+
+impl Display for LomenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LomenError::Connection(s) => write!(f, "Failed to connect to BIOS interface: {}", s),
+            LomenError::ClassNotFound(s) => {
+                write!(f, "`{}` not found. Is this an HP OMEN machine?", s)
+            }
+            LomenError::ReturnCode(code) => write!(f, "BIOS rejected the command: {}", code),
+            LomenError::InvalidResponse(s) => write!(f, "Invalid BIOS response: {}", s),
+            LomenError::Unsupported(s) => write!(f, "Not supported: {}", s),
+            LomenError::InvalidArgument(s) => write!(f, "Invalid argument: {}", s),
+            LomenError::Backend(s) => write!(f, "Backend error: {}", s),
+            LomenError::Io(e) => write!(f, "I/O error: {}", e),
+            LomenError::Cancelled => write!(f, "Animation was cancelled"),
+        }
+    }
+}
+
+impl Error for LomenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LomenError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LomenError {
+    fn from(value: io::Error) -> Self {
+        LomenError::Io(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_return_code_from_u32() {
+        assert_eq!(ReturnCode::UnknownCommandType, ReturnCode::from(4));
+        assert_eq!(ReturnCode::AccessDenied, ReturnCode::from(6));
+        assert_eq!(ReturnCode::Other(42), ReturnCode::from(42));
+    }
+
+    #[test]
+    fn test_return_code_roundtrip() {
+        for code in 1..10 {
+            assert_eq!(code, ReturnCode::from(code).code());
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            "BIOS rejected the command: access denied, run as administrator",
+            LomenError::ReturnCode(ReturnCode::AccessDenied).to_string()
+        );
+        assert_eq!(
+            "BIOS rejected the command: unknown error code 42",
+            LomenError::ReturnCode(ReturnCode::Other(42)).to_string()
+        );
+        assert_eq!(
+            "`hpqBIntM` not found. Is this an HP OMEN machine?",
+            LomenError::ClassNotFound("hpqBIntM".to_string()).to_string()
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        let error = LomenError::from(io::Error::new(io::ErrorKind::NotFound, "no led"));
+
+        assert_eq!(error.to_string(), error.clone().to_string());
+        assert!(matches!(
+            error.clone(),
+            LomenError::Io(e) if e.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_io_source() {
+        let error = LomenError::from(io::Error::from(io::ErrorKind::NotFound));
+
+        assert!(error.source().is_some());
+        assert!(
+            LomenError::ReturnCode(ReturnCode::NotSupported)
+                .source()
+                .is_none()
+        );
+    }
+}
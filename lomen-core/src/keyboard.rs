@@ -0,0 +1,143 @@
+use crate::error::LomenError;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Keyboard layout reported by keyboard type command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardType {
+    Standard,
+    WithNumpad,
+    TenKeyLess,
+    PerKeyRgb,
+    NoBacklight,
+    Other(u8),
+}
+
+/// Lighting features of keyboard type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardCapabilities {
+    /// Number of independently colored zones driven by zone colors commands
+    pub zone_count: u8,
+    /// Whether the keyboard has numeric keypad
+    pub numpad: bool,
+    /// Whether every key is lit independently. Such keyboards are not driven by zone commands.
+    pub per_key_rgb: bool,
+}
+
+/* Capability table */
+const FOUR_ZONE: KeyboardCapabilities = KeyboardCapabilities {
+    zone_count: 4,
+    numpad: false,
+    per_key_rgb: false,
+};
+const FOUR_ZONE_NUMPAD: KeyboardCapabilities = KeyboardCapabilities {
+    numpad: true,
+    ..FOUR_ZONE
+};
+const PER_KEY: KeyboardCapabilities = KeyboardCapabilities {
+    zone_count: 0,
+    numpad: true,
+    per_key_rgb: true,
+};
+const NO_BACKLIGHT: KeyboardCapabilities = KeyboardCapabilities {
+    zone_count: 0,
+    numpad: false,
+    per_key_rgb: false,
+};
+
+impl KeyboardType {
+    /// Returns raw keyboard type code
+    pub fn code(&self) -> u8 {
+        match self {
+            KeyboardType::Standard => 0x00,
+            KeyboardType::WithNumpad => 0x01,
+            KeyboardType::TenKeyLess => 0x02,
+            KeyboardType::PerKeyRgb => 0x03,
+            KeyboardType::NoBacklight => 0x04,
+            KeyboardType::Other(code) => *code,
+        }
+    }
+
+    /// Returns lighting features of the keyboard. Unknown types are assumed to be four-zone ones.
+    pub fn capabilities(&self) -> KeyboardCapabilities {
+        match self {
+            KeyboardType::Standard | KeyboardType::TenKeyLess | KeyboardType::Other(_) => FOUR_ZONE,
+            KeyboardType::WithNumpad => FOUR_ZONE_NUMPAD,
+            KeyboardType::PerKeyRgb => PER_KEY,
+            KeyboardType::NoBacklight => NO_BACKLIGHT,
+        }
+    }
+
+    /// Checks that zone colors commands can drive the keyboard
+    pub fn check_zone_lighting(&self) -> Result<(), LomenError> {
+        if self.capabilities().zone_count == 0 {
+            return Err(LomenError::Unsupported(format!(
+                "zone colors on {} keyboard",
+                self
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl From<u8> for KeyboardType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => KeyboardType::Standard,
+            0x01 => KeyboardType::WithNumpad,
+            0x02 => KeyboardType::TenKeyLess,
+            0x03 => KeyboardType::PerKeyRgb,
+            0x04 => KeyboardType::NoBacklight,
+            other => KeyboardType::Other(other),
+        }
+    }
+}
+
+impl Display for KeyboardType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyboardType::Standard => write!(f, "standard"),
+            KeyboardType::WithNumpad => write!(f, "standard with numpad"),
+            KeyboardType::TenKeyLess => write!(f, "tenkeyless"),
+            KeyboardType::PerKeyRgb => write!(f, "per-key RGB"),
+            KeyboardType::NoBacklight => write!(f, "no backlight"),
+            KeyboardType::Other(code) => write!(f, "unknown ({})", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keyboard_type_roundtrip() {
+        for code in 0..=255 {
+            assert_eq!(code, KeyboardType::from(code).code());
+        }
+        assert_eq!(KeyboardType::TenKeyLess, KeyboardType::from(2));
+        assert_eq!(KeyboardType::Other(9), KeyboardType::from(9));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(4, KeyboardType::Standard.capabilities().zone_count);
+        assert!(KeyboardType::WithNumpad.capabilities().numpad);
+        assert!(KeyboardType::PerKeyRgb.capabilities().per_key_rgb);
+        assert_eq!(0, KeyboardType::NoBacklight.capabilities().zone_count);
+        assert_eq!(4, KeyboardType::Other(7).capabilities().zone_count);
+    }
+
+    #[test]
+    fn test_check_zone_lighting() {
+        assert!(KeyboardType::TenKeyLess.check_zone_lighting().is_ok());
+        assert!(matches!(
+            KeyboardType::PerKeyRgb.check_zone_lighting(),
+            Err(LomenError::Unsupported(_))
+        ));
+        assert!(matches!(
+            KeyboardType::NoBacklight.check_zone_lighting(),
+            Err(LomenError::Unsupported(_))
+        ));
+    }
+}
@@ -1,3 +1,18 @@
+pub mod animation;
+pub mod backend;
+pub mod capabilities;
+pub mod clock;
 pub mod color;
-mod transition;
+pub mod color_space;
+pub mod compositor;
 pub mod control;
+pub mod device;
+pub mod easing;
+pub mod effect;
+pub mod error;
+pub mod keyboard;
+pub mod protocol;
+pub mod response;
+pub mod scheduler;
+pub mod timeline;
+pub mod transition;
@@ -0,0 +1,284 @@
+use crate::error::{LomenError, ReturnCode};
+
+/// Request signature ("SECU")
+pub const SIGN: [u8; 4] = [83, 69, 67, 85];
+
+/// Size of the data buffer exchanged with `hpqBIOSInt128`
+pub const BUFFER_SIZE: usize = 128;
+
+/* Command constants */
+pub const CMD_COMMON: u32 = 131081;
+pub const CMD_GAMING: u32 = 131080;
+
+/* Command type constants */
+pub const CMD_TYPE_GET_PLATFORM_INFO: u32 = 1;
+pub const CMD_TYPE_GET_ZONE_COLORS: u32 = 2;
+pub const CMD_TYPE_SET_ZONE_COLORS: u32 = 3;
+pub const CMD_TYPE_STATUS: u32 = 4;
+pub const CMD_TYPE_SET_BRIGHTNESS: u32 = 5;
+/// Light bar buffer is assumed to pair with set command like zone colors commands do
+pub const CMD_TYPE_GET_LIGHT_BAR_COLORS: u32 = 10;
+pub const CMD_TYPE_SET_LIGHT_BAR_COLORS: u32 = 11;
+pub const CMD_TYPE_GET_KEYBOARD_TYPE: u32 = 43;
+
+/* Platform info flags (first byte of platform info response) */
+pub const PLATFORM_LIGHTING_FLAG: u8 = 0b01;
+pub const PLATFORM_LIGHT_BAR_FLAG: u8 = 0b10;
+
+/* Lighting levels */
+pub const LIGHTING_LEVEL_ON: u8 = 228;
+pub const LIGHTING_LEVEL_OFF: u8 = 100;
+
+/// Bit of lighting level telling whether lighting is on. Other bits hold brightness in percent.
+pub const LIGHTING_ON_FLAG: u8 = 0x80;
+
+/// Maximal brightness in percent
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+/// Size of encoded request header (sign, command, command type and size)
+const REQUEST_HEADER_SIZE: usize = 16;
+
+/// Size of encoded response header (sign and return code)
+const RESPONSE_HEADER_SIZE: usize = 8;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Input of `hpqBIOSInt128` method (`hpqBDataIn`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BiosRequest {
+    pub sign: [u8; 4],
+    pub command: u32,
+    pub command_type: u32,
+    pub data: Vec<u8>,
+}
+
+impl BiosRequest {
+    /// Size of encoded request
+    pub const ENCODED_SIZE: usize = REQUEST_HEADER_SIZE + BUFFER_SIZE;
+
+    pub fn new(command: u32, command_type: u32) -> Self {
+        Self::with_data(command, command_type, &[])
+    }
+
+    pub fn with_data(command: u32, command_type: u32, data: &[u8]) -> Self {
+        Self {
+            sign: SIGN,
+            command,
+            command_type,
+            data: data.to_vec(),
+        }
+    }
+
+    /// Encodes request into `Sign[4] Command[u32] CommandType[u32] Size[u32] Data[128]`
+    /// little endian layout
+    pub fn encode(&self) -> Result<Vec<u8>, LomenError> {
+        if self.data.len() > BUFFER_SIZE {
+            return Err(LomenError::InvalidArgument(format!(
+                "request data size {} exceeds {} bytes",
+                self.data.len(),
+                BUFFER_SIZE
+            )));
+        }
+
+        let mut out = Vec::with_capacity(Self::ENCODED_SIZE);
+        out.extend_from_slice(&self.sign);
+        out.extend_from_slice(&self.command.to_le_bytes());
+        out.extend_from_slice(&self.command_type.to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out.resize(Self::ENCODED_SIZE, 0);
+
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, LomenError> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(LomenError::InvalidArgument(format!(
+                "encoded request must be {} bytes, but {} received",
+                Self::ENCODED_SIZE,
+                bytes.len()
+            )));
+        }
+
+        let size = read_u32(bytes, 12) as usize;
+        if size > BUFFER_SIZE {
+            return Err(LomenError::InvalidArgument(format!(
+                "request data size {} exceeds {} bytes",
+                size, BUFFER_SIZE
+            )));
+        }
+
+        Ok(Self {
+            sign: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            command: read_u32(bytes, 4),
+            command_type: read_u32(bytes, 8),
+            data: bytes[REQUEST_HEADER_SIZE..REQUEST_HEADER_SIZE + size].to_vec(),
+        })
+    }
+}
+
+/// Output of `hpqBIOSInt128` method (`hpqBDataOut128`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BiosResponse {
+    pub sign: [u8; 4],
+    pub return_code: u32,
+    pub data: Vec<u8>,
+}
+
+impl BiosResponse {
+    /// Creates successful response
+    pub fn ok(data: Vec<u8>) -> Self {
+        Self {
+            sign: SIGN,
+            return_code: 0,
+            data,
+        }
+    }
+
+    /// Creates response rejecting request with given code
+    pub fn error(code: ReturnCode) -> Self {
+        Self {
+            sign: SIGN,
+            return_code: code.code(),
+            data: vec![0; BUFFER_SIZE],
+        }
+    }
+
+    /// Encodes response into `Sign[4] ReturnCode[u32] Data[..]` little endian layout
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RESPONSE_HEADER_SIZE + self.data.len());
+        out.extend_from_slice(&self.sign);
+        out.extend_from_slice(&self.return_code.to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, LomenError> {
+        if bytes.len() < RESPONSE_HEADER_SIZE {
+            return Err(LomenError::InvalidResponse(format!(
+                "encoded response requires at least {} bytes, but {} received",
+                RESPONSE_HEADER_SIZE,
+                bytes.len()
+            )));
+        }
+
+        Ok(Self {
+            sign: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            return_code: read_u32(bytes, 4),
+            data: bytes[RESPONSE_HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    /// Returns response data if the request succeeded or decoded return code otherwise
+    pub fn into_data(self) -> Result<Vec<u8>, LomenError> {
+        match self.return_code {
+            0 => Ok(self.data),
+            code => Err(LomenError::ReturnCode(code.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_request() {
+        let request = BiosRequest::with_data(CMD_COMMON, CMD_TYPE_SET_ZONE_COLORS, &[0xAA, 0xBB]);
+        let bytes = request.encode().unwrap();
+
+        assert_eq!(BiosRequest::ENCODED_SIZE, bytes.len());
+        assert_eq!(b"SECU", &bytes[0..4]);
+        assert_eq!(&[0x09, 0x00, 0x02, 0x00], &bytes[4..8]);
+        assert_eq!(&[0x03, 0x00, 0x00, 0x00], &bytes[8..12]);
+        assert_eq!(&[0x02, 0x00, 0x00, 0x00], &bytes[12..16]);
+        assert_eq!(&[0xAA, 0xBB, 0x00], &bytes[16..19]);
+    }
+
+    #[test]
+    fn test_encode_oversized_request() {
+        let request = BiosRequest::with_data(CMD_COMMON, 0, &[0; BUFFER_SIZE + 1]);
+
+        assert!(matches!(
+            request.encode(),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_request_roundtrip() {
+        let request = BiosRequest::with_data(CMD_GAMING, CMD_TYPE_GET_KEYBOARD_TYPE, &[1, 2, 3]);
+
+        assert_eq!(
+            request,
+            BiosRequest::decode(&request.encode().unwrap()).unwrap()
+        );
+        assert_eq!(
+            BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS),
+            BiosRequest::decode(
+                &BiosRequest::new(CMD_COMMON, CMD_TYPE_GET_ZONE_COLORS)
+                    .encode()
+                    .unwrap()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_request_with_invalid_size() {
+        let mut bytes = BiosRequest::new(CMD_COMMON, 0).encode().unwrap();
+        bytes[12] = 0xFF;
+
+        assert!(BiosRequest::decode(&bytes).is_err());
+        assert!(BiosRequest::decode(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let response = BiosResponse::ok(vec![1, 2, 3]);
+        let bytes = response.encode();
+
+        assert_eq!(&[83, 69, 67, 85, 0, 0, 0, 0, 1, 2, 3], bytes.as_slice());
+        assert_eq!(response, BiosResponse::decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_decode_truncated_response() {
+        assert!(matches!(
+            BiosResponse::decode(&[83, 69, 67, 85, 0]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_response_into_data() {
+        assert_eq!(vec![7], BiosResponse::ok(vec![7]).into_data().unwrap());
+        assert!(matches!(
+            BiosResponse::error(ReturnCode::AccessDenied).into_data(),
+            Err(LomenError::ReturnCode(ReturnCode::AccessDenied))
+        ));
+    }
+
+    #[test]
+    fn test_decode_arbitrary_bytes() {
+        let mut seed: u32 = 0x1234_5678;
+        for len in 0..(BiosRequest::ENCODED_SIZE + 8) {
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (seed >> 24) as u8
+                })
+                .collect();
+
+            let _ = BiosRequest::decode(&bytes);
+            let _ = BiosResponse::decode(&bytes);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::color::{Color, LightingColors};
+use crate::error::LomenError;
+use crate::keyboard::KeyboardType;
+use crate::protocol::{
+    BUFFER_SIZE, LIGHTING_ON_FLAG, PLATFORM_LIGHT_BAR_FLAG, PLATFORM_LIGHTING_FLAG,
+};
+
+/* Zone indices */
+const RIGHT_ZONE_INDEX: usize = 0;
+const CENTER_ZONE_INDEX: usize = 1;
+const LEFT_ZONE_INDEX: usize = 2;
+const GAME_ZONE_INDEX: usize = 3;
+
+/// Offset of the first zone color in zone colors buffer
+const ZONE_COLORS_OFFSET: usize = 25;
+
+/// Minimal size of zone colors buffer holding all four zones
+pub const ZONE_COLORS_MIN_SIZE: usize = ZONE_COLORS_OFFSET + 4 * 3;
+
+fn check_size(data: &[u8], min_size: usize, name: &str) -> Result<(), LomenError> {
+    if data.len() < min_size {
+        return Err(LomenError::InvalidResponse(format!(
+            "{} requires at least {} bytes, but {} received",
+            name,
+            min_size,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Parses response of keyboard type command
+pub fn parse_keyboard_type(data: &[u8]) -> Result<KeyboardType, LomenError> {
+    check_size(data, 1, "keyboard type")?;
+    Ok(data[0].into())
+}
+
+/// Platform info reported by platform info command.
+///
+/// Only flags of the first byte are known, the rest of the payload is kept as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformInfo {
+    /// Whether keyboard lighting is supported
+    pub lighting_supported: bool,
+    /// Whether the device has a light bar
+    pub light_bar_supported: bool,
+    /// Raw response data
+    pub data: Vec<u8>,
+}
+
+impl PlatformInfo {
+    /// Parses response of platform info command
+    pub fn parse(data: Vec<u8>) -> Result<Self, LomenError> {
+        check_size(&data, 1, "platform info")?;
+        Ok(Self {
+            lighting_supported: (data[0] & PLATFORM_LIGHTING_FLAG) != 0,
+            light_bar_supported: (data[0] & PLATFORM_LIGHT_BAR_FLAG) != 0,
+            data,
+        })
+    }
+}
+
+/// Keyboard lighting status reported by status command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightingStatus {
+    /// Whether the backlight is lit
+    pub enabled: bool,
+    /// Backlight brightness in percent
+    pub brightness: u8,
+    /// Raw lighting level byte
+    pub level: u8,
+}
+
+impl LightingStatus {
+    /// Parses response of status command
+    pub fn parse(data: &[u8]) -> Result<Self, LomenError> {
+        check_size(data, 1, "lighting status")?;
+        Ok(Self::from(data[0]))
+    }
+}
+
+impl From<u8> for LightingStatus {
+    fn from(level: u8) -> Self {
+        Self {
+            enabled: (level & LIGHTING_ON_FLAG) != 0,
+            brightness: level & !LIGHTING_ON_FLAG,
+            level,
+        }
+    }
+}
+
+/// Zone colors buffer exchanged by get and set zone (or light bar) colors commands.
+///
+/// Bytes other than zone colors are opaque and kept intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneColors {
+    data: Vec<u8>,
+}
+
+impl Default for ZoneColors {
+    fn default() -> Self {
+        Self {
+            data: vec![0; BUFFER_SIZE],
+        }
+    }
+}
+
+impl ZoneColors {
+    /// Wraps response data checking it is large enough to hold all zones. Data beyond the BIOS
+    /// buffer size is dropped, so the buffer can be written back.
+    pub fn parse(mut data: Vec<u8>) -> Result<Self, LomenError> {
+        check_size(&data, ZONE_COLORS_MIN_SIZE, "zone colors")?;
+        data.truncate(BUFFER_SIZE);
+        Ok(Self { data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn rgb_offsets(zone_index: usize) -> (usize, usize, usize) {
+        let offset = ZONE_COLORS_OFFSET + zone_index * 3;
+        (offset, offset + 1, offset + 2)
+    }
+
+    pub fn zone_color(&self, zone_index: usize) -> Color {
+        let (ri, gi, bi) = Self::rgb_offsets(zone_index);
+        Color::new(self.data[ri], self.data[gi], self.data[bi])
+    }
+
+    /// Sets zone color. `None` leaves the zone unchanged.
+    pub fn set_zone_color(&mut self, zone_index: usize, color: Option<Color>) {
+        if let Some(c) = color {
+            let (ri, gi, bi) = Self::rgb_offsets(zone_index);
+            self.data[ri] = c.r;
+            self.data[gi] = c.g;
+            self.data[bi] = c.b;
+        }
+    }
+
+    pub fn colors(&self) -> LightingColors {
+        LightingColors {
+            right: Some(self.zone_color(RIGHT_ZONE_INDEX)),
+            center: Some(self.zone_color(CENTER_ZONE_INDEX)),
+            left: Some(self.zone_color(LEFT_ZONE_INDEX)),
+            game: Some(self.zone_color(GAME_ZONE_INDEX)),
+        }
+    }
+
+    /// Sets colors of all zones. Unset zones are left unchanged.
+    pub fn set_colors(&mut self, colors: &LightingColors) {
+        self.set_zone_color(RIGHT_ZONE_INDEX, colors.right);
+        self.set_zone_color(CENTER_ZONE_INDEX, colors.center);
+        self.set_zone_color(LEFT_ZONE_INDEX, colors.left);
+        self.set_zone_color(GAME_ZONE_INDEX, colors.game);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_keyboard_type() {
+        assert_eq!(KeyboardType::PerKeyRgb, parse_keyboard_type(&[3]).unwrap());
+        assert_eq!(
+            KeyboardType::PerKeyRgb,
+            parse_keyboard_type(&[3; BUFFER_SIZE * 2]).unwrap()
+        );
+        assert!(matches!(
+            parse_keyboard_type(&[]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_platform_info() {
+        assert_eq!(
+            PlatformInfo {
+                lighting_supported: true,
+                light_bar_supported: true,
+                data: vec![0b11, 0xFF],
+            },
+            PlatformInfo::parse(vec![0b11, 0xFF]).unwrap()
+        );
+
+        let info = PlatformInfo::parse(vec![0b10]).unwrap();
+        assert!(!info.lighting_supported);
+        assert!(info.light_bar_supported);
+
+        let info = PlatformInfo::parse(vec![0b01]).unwrap();
+        assert!(info.lighting_supported);
+        assert!(!info.light_bar_supported);
+
+        assert!(matches!(
+            PlatformInfo::parse(vec![]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_lighting_status() {
+        assert_eq!(
+            LightingStatus {
+                enabled: true,
+                brightness: 100,
+                level: 228,
+            },
+            LightingStatus::parse(&[228, 0xFF]).unwrap()
+        );
+        assert!(!LightingStatus::parse(&[100]).unwrap().enabled);
+        assert!(matches!(
+            LightingStatus::parse(&[]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_zone_colors() {
+        assert!(matches!(
+            ZoneColors::parse(vec![]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            ZoneColors::parse(vec![0; ZONE_COLORS_MIN_SIZE - 1]),
+            Err(LomenError::InvalidResponse(_))
+        ));
+        assert!(ZoneColors::parse(vec![0; ZONE_COLORS_MIN_SIZE]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_oversized_zone_colors() {
+        let mut data = vec![0xEE; BUFFER_SIZE + 16];
+        data[25..37].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        let zone_colors = ZoneColors::parse(data.clone()).unwrap();
+
+        assert_eq!(Some(Color::new(10, 11, 12)), zone_colors.colors().game);
+        // Bytes beyond the buffer size cannot be written back
+        assert_eq!(data[..BUFFER_SIZE], *zone_colors.as_bytes());
+    }
+
+    #[test]
+    fn test_set_colors() {
+        let mut zone_colors = ZoneColors::default();
+        zone_colors.set_colors(&LightingColors {
+            right: Some(Color::from(0xAABBCC)),
+            center: None,
+            left: None,
+            game: Some(Color::from(0x112233)),
+        });
+
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0xAABBCC)),
+                center: Some(Color::from(0x000000)),
+                left: Some(Color::from(0x000000)),
+                game: Some(Color::from(0x112233)),
+            },
+            zone_colors.colors()
+        );
+        assert_eq!(BUFFER_SIZE, zone_colors.as_bytes().len());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::clock::{Clock, SystemClock};
+use crate::error::LomenError;
+use crate::transition::progress;
+use std::time::Duration;
+
+/// Statistics of played animation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Number of frames written
+    pub frames: u32,
+    /// Number of frames skipped because previous ones took too long
+    pub dropped: u32,
+    /// Wall time the animation took
+    pub elapsed: Duration,
+}
+
+impl FrameStats {
+    /// Returns achieved frame rate
+    pub fn fps(&self) -> f32 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.frames as f32 / self.elapsed.as_secs_f32()
+    }
+}
+
+/// Renders frames at absolute deadlines `start + i * period`.
+///
+/// A frame that misses its deadline is rendered late and the deadlines it overran are dropped,
+/// so slow rendering never stretches total duration. The last frame is always rendered with
+/// progress `1.0`. Endless schedulers render until `render` fails.
+pub struct FrameScheduler {
+    duration: Option<Duration>,
+    period: Duration,
+}
+
+impl FrameScheduler {
+    pub fn new(duration: Duration, fps: u8) -> Result<Self, LomenError> {
+        Ok(Self {
+            duration: Some(duration),
+            ..Self::endless(fps)?
+        })
+    }
+
+    /// Creates scheduler rendering frames until cancelled, e.g. by failing render
+    pub fn endless(fps: u8) -> Result<Self, LomenError> {
+        if fps == 0 {
+            return Err(LomenError::InvalidArgument(
+                "fps must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            duration: None,
+            period: Duration::from_secs(1) / fps as u32,
+        })
+    }
+
+    /// Calls `render` with progress in range `0.0..=1.0` for every frame. Progress of endless
+    /// scheduler is always `0.0`.
+    pub fn run(
+        &self,
+        render: impl FnMut(f32) -> Result<(), LomenError>,
+    ) -> Result<FrameStats, LomenError> {
+        self.run_with_clock(&SystemClock::new(), render)
+    }
+
+    /// Same as [`FrameScheduler::run`] but takes time from given clock
+    pub fn run_with_clock(
+        &self,
+        clock: &dyn Clock,
+        mut render: impl FnMut(f32) -> Result<(), LomenError>,
+    ) -> Result<FrameStats, LomenError> {
+        self.run_timed_with_clock(clock, |elapsed| {
+            render(self.duration.map_or(0.0, |d| progress(elapsed, d)))
+        })
+    }
+
+    /// Calls `render` with time elapsed since the start for every frame
+    pub fn run_timed(
+        &self,
+        render: impl FnMut(Duration) -> Result<(), LomenError>,
+    ) -> Result<FrameStats, LomenError> {
+        self.run_timed_with_clock(&SystemClock::new(), render)
+    }
+
+    /// Same as [`FrameScheduler::run_timed`] but takes time from given clock
+    pub fn run_timed_with_clock(
+        &self,
+        clock: &dyn Clock,
+        mut render: impl FnMut(Duration) -> Result<(), LomenError>,
+    ) -> Result<FrameStats, LomenError> {
+        let start = clock.elapsed();
+        let period = self.period.as_nanos();
+        let frame_count = self.duration.map_or(u64::MAX, |duration| {
+            saturating_u64(duration.as_nanos().div_ceil(period))
+        });
+        let mut frames: u32 = 0;
+        let mut dropped: u32 = 0;
+        let mut index: u64 = 0;
+
+        loop {
+            let elapsed = clock.elapsed() - start;
+            if self.duration.is_some_and(|duration| elapsed >= duration) {
+                break;
+            }
+
+            render(elapsed)?;
+            frames = frames.saturating_add(1);
+
+            let now = clock.elapsed() - start;
+            let next_index = saturating_u64(now.as_nanos() / period)
+                .saturating_add(1)
+                .min(frame_count);
+            let skipped = next_index.saturating_sub(index).saturating_sub(1);
+            dropped = dropped.saturating_add(u32::try_from(skipped).unwrap_or(u32::MAX));
+            index = next_index;
+
+            let deadline = frame_time(self.period, index);
+            if let Some(duration) = self.duration
+                && deadline >= duration
+            {
+                clock.sleep_until(start.saturating_add(duration));
+                break;
+            }
+            clock.sleep_until(start.saturating_add(deadline));
+        }
+
+        // Only finite schedulers get here
+        render(self.duration.unwrap_or_default())?;
+        frames = frames.saturating_add(1);
+
+        Ok(FrameStats {
+            frames,
+            dropped,
+            elapsed: clock.elapsed() - start,
+        })
+    }
+}
+
+fn saturating_u64(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// Returns start time of frame `index`, saturating at [`Duration::MAX`]
+fn frame_time(period: Duration, index: u64) -> Duration {
+    let nanos = period.as_nanos().saturating_mul(index as u128);
+    match u64::try_from(nanos / 1_000_000_000) {
+        Ok(secs) => Duration::new(secs, (nanos % 1_000_000_000) as u32),
+        Err(_) => Duration::MAX,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_last_frame_is_target() {
+        let scheduler = FrameScheduler::new(Duration::from_millis(50), 100).unwrap();
+        let mut progress = Vec::new();
+
+        let stats = scheduler
+            .run(|p| {
+                progress.push(p);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(Some(&1.0), progress.last());
+        assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(progress.len() as u32, stats.frames);
+        assert!(stats.elapsed >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_frames_at_deadlines() {
+        let scheduler = FrameScheduler::new(Duration::from_millis(100), 20).unwrap();
+        let clock = ManualClock::new();
+        let mut progress = Vec::new();
+
+        let stats = scheduler
+            .run_with_clock(&clock, |p| {
+                progress.push(p);
+                clock.advance(Duration::from_millis(1));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(vec![0.0, 0.5, 1.0], progress);
+        assert_eq!(0, stats.dropped);
+        assert_eq!(Duration::from_millis(101), stats.elapsed);
+    }
+
+    #[test]
+    fn test_slow_frames_are_dropped() {
+        let scheduler = FrameScheduler::new(Duration::from_millis(100), 100).unwrap();
+        let clock = ManualClock::new();
+        let mut progress = Vec::new();
+
+        let stats = scheduler
+            .run_with_clock(&clock, |p| {
+                progress.push(p);
+                clock.advance(Duration::from_millis(25));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(vec![0.0, 0.3, 0.6, 0.9, 1.0], progress);
+        assert_eq!(6, stats.dropped);
+        assert_eq!(5, stats.frames);
+        assert_eq!(Duration::from_millis(140), stats.elapsed);
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let scheduler = FrameScheduler::new(Duration::ZERO, 50).unwrap();
+        let mut progress = Vec::new();
+
+        scheduler
+            .run(|p| {
+                progress.push(p);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(vec![1.0], progress);
+    }
+
+    #[test]
+    fn test_endless() {
+        let scheduler = FrameScheduler::endless(10).unwrap();
+        let clock = ManualClock::new();
+        let mut elapsed = Vec::new();
+
+        let result = scheduler.run_timed_with_clock(&clock, |t| {
+            if elapsed.len() == 4 {
+                return Err(LomenError::Cancelled);
+            }
+            elapsed.push(t);
+            clock.advance(Duration::from_millis(150));
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(LomenError::Cancelled)));
+        assert_eq!(
+            vec![0, 200, 400, 600],
+            elapsed.iter().map(|t| t.as_millis()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_zero_fps() {
+        assert!(matches!(
+            FrameScheduler::new(Duration::from_secs(1), 0),
+            Err(LomenError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            FrameScheduler::endless(0),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_long_running() {
+        let scheduler = FrameScheduler::new(Duration::from_secs(1 << 33), 1).unwrap();
+        let clock = ManualClock::new();
+        let mut elapsed = Vec::new();
+
+        let stats = scheduler
+            .run_timed_with_clock(&clock, |t| {
+                elapsed.push(t.as_secs());
+                clock.advance(Duration::from_secs(1 << 32));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(vec![0, (1 << 32) + 1, 1 << 33], elapsed);
+        assert_eq!(3, stats.frames);
+        assert_eq!(u32::MAX, stats.dropped);
+    }
+
+    #[test]
+    fn test_endless_past_u32_frames() {
+        let scheduler = FrameScheduler::endless(1).unwrap();
+        let clock = ManualClock::new();
+        let mut elapsed = Vec::new();
+
+        let result = scheduler.run_timed_with_clock(&clock, |t| {
+            if elapsed.len() == 3 {
+                return Err(LomenError::Cancelled);
+            }
+            elapsed.push(t.as_secs());
+            clock.advance(Duration::from_secs(u32::MAX as u64 - 1));
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(LomenError::Cancelled)));
+        assert_eq!(vec![0, u32::MAX as u64, 2 * u32::MAX as u64], elapsed);
+    }
+}
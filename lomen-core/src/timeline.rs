@@ -0,0 +1,533 @@
+use crate::color::{Color, LightingColors};
+use crate::color_space::ColorSpace;
+use crate::easing::Easing;
+use crate::effect::Effect;
+use crate::error::LomenError;
+use serde::Deserialize;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Frame rate used when the file does not specify one
+const DEFAULT_FPS: u8 = 50;
+
+/* Raw file model, see `Timeline::parse` for the format */
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct TimelineFile {
+    fps: Option<u8>,
+    repeat: Option<String>,
+    count: Option<u32>,
+    easing: Option<String>,
+    color_space: Option<String>,
+    tracks: TracksFile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TracksFile {
+    all: Option<Vec<KeyframeFile>>,
+    right: Option<Vec<KeyframeFile>>,
+    center: Option<Vec<KeyframeFile>>,
+    left: Option<Vec<KeyframeFile>>,
+    game: Option<Vec<KeyframeFile>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeFile {
+    time: f32,
+    color: String,
+    easing: Option<String>,
+}
+
+/// How the timeline continues after its last keyframe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Repeat {
+    /// Plays once and holds the last keyframes
+    #[default]
+    Once,
+    /// Starts over from the first keyframes
+    Loop,
+    /// Plays forwards and backwards in turn
+    PingPong,
+}
+
+impl FromStr for Repeat {
+    type Err = LomenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "once" => Ok(Repeat::Once),
+            "loop" => Ok(Repeat::Loop),
+            "ping-pong" => Ok(Repeat::PingPong),
+            _ => Err(LomenError::InvalidArgument(format!(
+                "unknown repeat `{}`, expected once, loop or ping-pong",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for Repeat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Repeat::Once => write!(f, "once"),
+            Repeat::Loop => write!(f, "loop"),
+            Repeat::PingPong => write!(f, "ping-pong"),
+        }
+    }
+}
+
+/// Color of zone at given time. Easing applies to the segment from this keyframe to the next
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: Duration,
+    pub color: Color,
+    pub easing: Easing,
+}
+
+/// Keyframes of single zone sorted by time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    /// Creates track checking that it has keyframes and their times increase
+    pub fn new(keyframes: Vec<Keyframe>) -> Result<Self, LomenError> {
+        if keyframes.is_empty() {
+            return Err(LomenError::InvalidArgument(
+                "track has no keyframes".to_string(),
+            ));
+        }
+        if keyframes.windows(2).any(|w| w[0].time >= w[1].time) {
+            return Err(LomenError::InvalidArgument(
+                "keyframe times must increase".to_string(),
+            ));
+        }
+        Ok(Self { keyframes })
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Returns time of the last keyframe
+    pub fn duration(&self) -> Duration {
+        self.keyframes.last().map(|k| k.time).unwrap_or_default()
+    }
+
+    /// Returns color at time `t`. Colors before the first and after the last keyframe are held.
+    pub fn sample(&self, t: Duration, color_space: ColorSpace) -> Color {
+        let next = self.keyframes.partition_point(|k| k.time <= t);
+        if next == 0 {
+            return self.keyframes[0].color;
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1].color;
+        }
+
+        let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let local = (t - from.time).div_duration_f32(to.time - from.time);
+        color_space.interpolate(from.color, to.color, from.easing.apply(local))
+    }
+}
+
+/// Multi-step keyframe animation of keyboard zones.
+///
+/// Zones without a track are left unchanged. One cycle lasts until the last keyframe of all
+/// tracks; shorter tracks hold their last color until the cycle ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub fps: u8,
+    pub repeat: Repeat,
+    /// Number of cycles played by repeating timeline, `None` repeats until cancelled
+    pub count: Option<u32>,
+    pub color_space: ColorSpace,
+    pub right: Option<Track>,
+    pub center: Option<Track>,
+    pub left: Option<Track>,
+    pub game: Option<Track>,
+}
+
+impl Timeline {
+    /// Loads animation file. JSON files start with `{`, anything else is read as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LomenError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot read {}: {}", path.display(), e))
+        })?;
+        Self::parse(&content)
+    }
+
+    /// Parses animation in TOML or JSON format and validates it.
+    ///
+    /// ```toml
+    /// fps = 50                  # optional, 50 by default
+    /// repeat = "ping-pong"      # once (default), loop or ping-pong
+    /// count = 3                 # optional number of cycles of repeating animation
+    /// easing = "ease-in-out"    # optional default easing of keyframes
+    /// color-space = "oklab"     # optional interpolation color space
+    ///
+    /// [tracks]                  # right, center, left, game or all (zones without own track)
+    /// right = [
+    ///     { time = 0.0, color = "#FF0000" },
+    ///     { time = 1.5, color = "#0000FF", easing = "steps(3)" },
+    ///     { time = 3.0, color = "#FF0000" },
+    /// ]
+    /// ```
+    pub fn parse(content: &str) -> Result<Self, LomenError> {
+        let file: TimelineFile = if content.trim_start().starts_with('{') {
+            serde_json::from_str(content).map_err(|e| invalid_file(&e))?
+        } else {
+            toml::from_str(content).map_err(|e| invalid_file(&e))?
+        };
+        Self::from_file(file)
+    }
+
+    fn from_file(file: TimelineFile) -> Result<Self, LomenError> {
+        let easing = match &file.easing {
+            Some(easing) => easing.parse()?,
+            None => Easing::Linear,
+        };
+        let track = |name: &str, keyframes: &Option<Vec<KeyframeFile>>| {
+            keyframes
+                .as_ref()
+                .or(file.tracks.all.as_ref())
+                .map(|keyframes| parse_track(keyframes, easing))
+                .transpose()
+                .map_err(|e| match e {
+                    LomenError::InvalidArgument(s) => {
+                        LomenError::InvalidArgument(format!("{} track: {}", name, s))
+                    }
+                    e => e,
+                })
+        };
+
+        let timeline = Self {
+            fps: file.fps.unwrap_or(DEFAULT_FPS),
+            repeat: match &file.repeat {
+                Some(repeat) => repeat.parse()?,
+                None => Repeat::Once,
+            },
+            count: file.count,
+            color_space: match &file.color_space {
+                Some(color_space) => color_space.parse()?,
+                None => ColorSpace::Srgb,
+            },
+            right: track("right", &file.tracks.right)?,
+            center: track("center", &file.tracks.center)?,
+            left: track("left", &file.tracks.left)?,
+            game: track("game", &file.tracks.game)?,
+        };
+        timeline.validate()?;
+        Ok(timeline)
+    }
+
+    /// Checks that the timeline can be played
+    pub fn validate(&self) -> Result<(), LomenError> {
+        let invalid = |s: &str| Err(LomenError::InvalidArgument(s.to_string()));
+
+        if self.tracks().iter().all(Option::is_none) {
+            return invalid("animation has no tracks");
+        }
+        if self.fps == 0 {
+            return invalid("fps must be greater than zero");
+        }
+        if self.count == Some(0) {
+            return invalid("count must be greater than zero");
+        }
+        if self.repeat != Repeat::Once && self.duration().is_zero() {
+            return invalid("repeating animation must last longer than zero");
+        }
+        Ok(())
+    }
+
+    fn tracks(&self) -> [Option<&Track>; 4] {
+        [
+            self.right.as_ref(),
+            self.center.as_ref(),
+            self.left.as_ref(),
+            self.game.as_ref(),
+        ]
+    }
+
+    /// Returns duration of one cycle
+    pub fn duration(&self) -> Duration {
+        self.tracks()
+            .iter()
+            .flatten()
+            .map(|track| track.duration())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns duration of all cycles, `None` if the timeline repeats until cancelled. Cycles
+    /// lasting longer than representable duration are played until cancelled too.
+    pub fn total_duration(&self) -> Option<Duration> {
+        match (self.repeat, self.count) {
+            (Repeat::Once, _) => Some(self.duration()),
+            (_, Some(count)) => self.duration().checked_mul(count),
+            (_, None) => None,
+        }
+    }
+
+    /// Maps time since the start to time within a cycle
+    fn cycle_time(&self, t: Duration) -> Duration {
+        let duration = self.duration();
+        if duration.is_zero() {
+            return Duration::ZERO;
+        }
+
+        // Finished timeline holds its final frame
+        if let Some(total) = self.total_duration()
+            && t >= total
+        {
+            let forwards = self.repeat != Repeat::PingPong || self.count.unwrap_or(1) % 2 == 1;
+            return if forwards { duration } else { Duration::ZERO };
+        }
+
+        let cycle = (t.as_nanos() / duration.as_nanos()) as u64;
+        let offset = Duration::from_nanos((t.as_nanos() % duration.as_nanos()) as u64);
+        match self.repeat {
+            Repeat::Once => t.min(duration),
+            Repeat::Loop => offset,
+            Repeat::PingPong if cycle % 2 == 1 => duration - offset,
+            Repeat::PingPong => offset,
+        }
+    }
+
+    /// Returns colors at time `t` since the start
+    pub fn sample(&self, t: Duration) -> LightingColors {
+        let t = self.cycle_time(t);
+        let [right, center, left, game] = self
+            .tracks()
+            .map(|track| track.map(|track| track.sample(t, self.color_space)));
+        LightingColors {
+            right,
+            center,
+            left,
+            game,
+        }
+    }
+}
+
+impl Effect for Timeline {
+    fn frame(&mut self, t: Duration) -> LightingColors {
+        self.sample(t)
+    }
+}
+
+fn invalid_file(error: &dyn Display) -> LomenError {
+    LomenError::InvalidArgument(format!("invalid animation file: {}", error))
+}
+
+fn parse_track(keyframes: &[KeyframeFile], default_easing: Easing) -> Result<Track, LomenError> {
+    let keyframes = keyframes
+        .iter()
+        .map(|keyframe| {
+            if !keyframe.time.is_finite() || keyframe.time < 0.0 {
+                return Err(LomenError::InvalidArgument(format!(
+                    "keyframe time must be a non-negative number of seconds, but {} given",
+                    keyframe.time
+                )));
+            }
+
+            let time = Duration::try_from_secs_f32(keyframe.time).map_err(|_| {
+                LomenError::InvalidArgument(format!(
+                    "keyframe time {} is out of range",
+                    keyframe.time
+                ))
+            })?;
+
+            Ok(Keyframe {
+                time,
+                color: keyframe
+                    .color
+                    .parse()
+                    .map_err(LomenError::InvalidArgument)?,
+                easing: match &keyframe.easing {
+                    Some(easing) => easing.parse()?,
+                    None => default_easing,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Track::new(keyframes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PULSE: &str = r##"
+        repeat = "ping-pong"
+        count = 3
+        easing = "ease-in-out"
+
+        [tracks]
+        all = [
+            { time = 0.0, color = "#000000" },
+            { time = 1.0, color = "#FF0000", easing = "linear" },
+            { time = 2.0, color = "#FFFFFF" },
+        ]
+        game = [{ time = 0.5, color = "#0000FF" }]
+    "##;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn color(color: u64) -> Option<Color> {
+        Some(Color::from(color))
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let timeline = Timeline::parse(PULSE).unwrap();
+
+        assert_eq!(Repeat::PingPong, timeline.repeat);
+        assert_eq!(Some(3), timeline.count);
+        assert_eq!(DEFAULT_FPS, timeline.fps);
+        assert_eq!(timeline.right, timeline.left);
+        assert_eq!(
+            Easing::EaseInOut,
+            timeline.right.as_ref().unwrap().keyframes()[0].easing
+        );
+        assert_eq!(
+            Easing::Linear,
+            timeline.right.as_ref().unwrap().keyframes()[1].easing
+        );
+        assert_eq!(1, timeline.game.as_ref().unwrap().keyframes().len());
+        assert_eq!(ms(2000), timeline.duration());
+        assert_eq!(Some(ms(6000)), timeline.total_duration());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let timeline = Timeline::parse(
+            r##"{
+                "repeat": "loop",
+                "color-space": "hsl",
+                "tracks": {
+                    "center": [
+                        { "time": 0, "color": "#FF0000" },
+                        { "time": 1, "color": "#0000FF" }
+                    ]
+                }
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(Repeat::Loop, timeline.repeat);
+        assert_eq!(ColorSpace::Hsl, timeline.color_space);
+        assert_eq!(None, timeline.right);
+        assert_eq!(None, timeline.total_duration());
+        assert_eq!(color(0xFF00FF), timeline.sample(ms(500)).center);
+        assert_eq!(None, timeline.sample(ms(500)).right);
+    }
+
+    #[test]
+    fn test_sample() {
+        let timeline = Timeline::parse(PULSE).unwrap();
+
+        let colors = timeline.sample(ms(0));
+        assert_eq!(color(0x000000), colors.right);
+        assert_eq!(color(0x0000FF), colors.game);
+
+        // Eased first segment, linear second one
+        assert_eq!(color(0x7F0000), timeline.sample(ms(500)).right);
+        assert_eq!(color(0xFF7F7F), timeline.sample(ms(1500)).right);
+        assert_eq!(color(0xFFFFFF), timeline.sample(ms(2000)).right);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let timeline = Timeline::parse(PULSE).unwrap();
+
+        // Second cycle goes backwards, the third one forwards again
+        assert_eq!(timeline.sample(ms(1500)), timeline.sample(ms(2500)));
+        assert_eq!(timeline.sample(ms(500)), timeline.sample(ms(4500)));
+        // Finished timeline holds the end of the last cycle
+        assert_eq!(color(0xFFFFFF), timeline.sample(ms(6000)).right);
+        assert_eq!(color(0xFFFFFF), timeline.sample(ms(9000)).right);
+
+        let looped = Timeline {
+            repeat: Repeat::Loop,
+            count: None,
+            ..timeline.clone()
+        };
+        assert_eq!(looped.sample(ms(500)), looped.sample(ms(4500)));
+
+        let once = Timeline {
+            repeat: Repeat::Once,
+            ..timeline
+        };
+        assert_eq!(once.sample(ms(2000)), once.sample(ms(4500)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let invalid = [
+            "[tracks]",
+            "[tracks]\nright = []",
+            "fps = 0\n[tracks]\nright = [{ time = 0, color = \"#FF0000\" }]",
+            "[tracks]\nright = [{ time = -1, color = \"#FF0000\" }]",
+            "[tracks]\nright = [{ time = 1e30, color = \"#FF0000\" }]",
+            "[tracks]\nright = [{ time = 0, color = \"red\" }]",
+            "[tracks]\nright = [{ time = 1, color = \"#FF0000\" }, { time = 1, color = \"#00FF00\" }]",
+            "[tracks]\nright = [{ time = 0, color = \"#FF0000\", easing = \"bounce\" }]",
+            "repeat = \"loop\"\n[tracks]\nright = [{ time = 0, color = \"#FF0000\" }]",
+            "repeat = \"loop\"\ncount = 0\n[tracks]\nright = [{ time = 1, color = \"#FF0000\" }]",
+            "repeat = \"forever\"\n[tracks]\nright = [{ time = 1, color = \"#FF0000\" }]",
+            "speed = 2\n[tracks]\nright = [{ time = 1, color = \"#FF0000\" }]",
+            "{ \"tracks\": ",
+        ];
+
+        for content in invalid {
+            assert!(
+                matches!(
+                    Timeline::parse(content),
+                    Err(LomenError::InvalidArgument(_))
+                ),
+                "{}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_names_track() {
+        let error = Timeline::parse("[tracks]\nleft = []").unwrap_err();
+        assert_eq!(
+            "Invalid argument: left track: track has no keyframes",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_endless_on_overflow() {
+        let timeline = Timeline::parse(
+            "repeat = \"ping-pong\"\ncount = 4294967295\n[tracks]\nright = [{ time = 1e10, color = \"#FF0000\" }]",
+        )
+        .unwrap();
+
+        assert_eq!(None, timeline.total_duration());
+        assert_eq!(color(0xFF0000), timeline.sample(Duration::MAX).right);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        assert!(matches!(
+            Timeline::load("missing-animation.toml"),
+            Err(LomenError::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+    }
+}
@@ -1,89 +1,484 @@
-﻿use crate::color::{Color, LightingColors};
-use std::time::{Duration, Instant};
-
-struct ColorTransition {
-    from: Color,
-    to: Color,
-    start_at: Instant,
-    duration: Duration,
-    is_finished: bool,
+use crate::clock::Clock;
+use crate::color::{Color, LightingColors};
+use crate::color_space::{ColorSpace, to_rgb};
+use crate::easing::Easing;
+use crate::error::LomenError;
+use crate::scheduler::FrameScheduler;
+use std::time::Duration;
+
+/// Parameters of animated colors change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionOptions {
+    pub duration: Duration,
+    pub fps: u8,
+    pub easing: Easing,
+    pub color_space: ColorSpace,
 }
 
-impl ColorTransition {
-    pub fn new(from: Option<Color>, to: Option<Color>, duration: Duration) -> Self {
+impl TransitionOptions {
+    /// Creates options of linear transition in sRGB space
+    pub fn new(duration: Duration, fps: u8) -> Self {
         Self {
-            from: from.unwrap_or(Color::new(0, 0, 0)),
-            to: to.unwrap_or(Color::new(0, 0, 0)),
-            start_at: Instant::now(),
             duration,
-            is_finished: from.is_none() || to.is_none(),
+            fps,
+            easing: Easing::Linear,
+            color_space: ColorSpace::Srgb,
         }
     }
-}
 
-impl Iterator for ColorTransition {
-    type Item = Color;
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.is_finished {
-            return None;
+    pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+        Self {
+            color_space,
+            ..self
         }
+    }
 
-        let elapsed = self.start_at.elapsed();
+    /// Checks that transition with these options can be played
+    pub fn validate(&self) -> Result<(), LomenError> {
+        FrameScheduler::new(self.duration, self.fps).map(|_| ())
+    }
+}
+
+/// Returns progress of transition lasting `duration` after `elapsed` time, in range `0.0..=1.0`
+pub fn progress(elapsed: Duration, duration: Duration) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    elapsed.div_duration_f32(duration).min(1.0)
+}
 
-        let mut factor = elapsed.div_duration_f32(self.duration);
-        if factor >= 1.0 {
-            factor = 1.0;
-            self.is_finished = true;
+/// Rate of change of RGB channels of every zone in channel units per second
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LightingVelocity {
+    pub right: [f32; 3],
+    pub center: [f32; 3],
+    pub left: [f32; 3],
+    pub game: [f32; 3],
+}
+
+impl LightingVelocity {
+    fn zones(&self) -> [[f32; 3]; 4] {
+        [self.right, self.center, self.left, self.game]
+    }
+
+    fn from_zones([right, center, left, game]: [[f32; 3]; 4]) -> Self {
+        Self {
+            right,
+            center,
+            left,
+            game,
         }
+    }
+}
 
-        let interpolate = |start: u8, end: u8, f: f32| -> u8 {
-            (start as f32 + (end as i16 - start as i16) as f32 * f) as u8
-        };
+/// Transition between two colors of single zone.
+///
+/// Unset ends are resolved the same way as by setting colors: an unset target keeps the source
+/// color, i.e. the zone is left unchanged, and an unset source (unknown current color) fades
+/// from black. Only a transition with both ends unset yields no color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorTransition {
+    from: Option<Color>,
+    to: Option<Color>,
+}
+
+impl ColorTransition {
+    pub fn new(from: Option<Color>, to: Option<Color>) -> Self {
+        Self { from, to }
+    }
 
-        Some(Color::new(
-            interpolate(self.from.r, self.to.r, factor),
-            interpolate(self.from.g, self.to.g, factor),
-            interpolate(self.from.b, self.to.b, factor),
-        ))
+    /// Returns color at given progress interpolated in sRGB space
+    pub fn sample(&self, progress: f32) -> Option<Color> {
+        self.sample_in(ColorSpace::Srgb, progress)
+    }
+
+    /// Returns color at given progress interpolated in given color space
+    pub fn sample_in(&self, color_space: ColorSpace, progress: f32) -> Option<Color> {
+        self.sample_rgb(color_space, progress)
+            .map(|rgb| color_space.quantize(rgb))
+    }
+
+    /// Returns unrounded sRGB channels at given progress
+    fn sample_rgb(&self, color_space: ColorSpace, progress: f32) -> Option<[f32; 3]> {
+        match (self.from, self.to) {
+            (from, Some(to)) => {
+                let from = from.unwrap_or(Color::new(0, 0, 0));
+                Some(color_space.mix(from, to, progress))
+            }
+            (from, None) => from.map(to_rgb),
+        }
     }
 }
 
-pub(crate) struct LightingColorsTransition {
+/// Transition between lighting colors of all zones.
+///
+/// It does not track time by itself: sample it at a progress or with a [`Clock`].
+///
+/// A transition may start moving, e.g. when it replaces another one mid-flight. The initial
+/// velocity is added along Hermite basis `h10(s) = s(1 - s)^2` which starts with slope 1 and
+/// vanishes at both ends, so the transition still starts and ends at its colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightingColorsTransition {
     right: ColorTransition,
     center: ColorTransition,
     left: ColorTransition,
     game: ColorTransition,
+    easing: Easing,
+    color_space: ColorSpace,
+    /// Initial velocity scaled to channel units per whole transition
+    momentum: LightingVelocity,
 }
 
 impl LightingColorsTransition {
-    pub fn new(from: LightingColors, to: LightingColors, duration: Duration) -> Self {
+    pub fn new(from: LightingColors, to: LightingColors) -> Self {
         Self {
-            right: ColorTransition::new(from.right, to.right, duration),
-            center: ColorTransition::new(from.center, to.center, duration),
-            left: ColorTransition::new(from.left, to.left, duration),
-            game: ColorTransition::new(from.game, to.game, duration),
+            right: ColorTransition::new(from.right, to.right),
+            center: ColorTransition::new(from.center, to.center),
+            left: ColorTransition::new(from.left, to.left),
+            game: ColorTransition::new(from.game, to.game),
+            easing: Easing::Linear,
+            color_space: ColorSpace::Srgb,
+            momentum: LightingVelocity::default(),
+        }
+    }
+
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
+
+    pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+        Self {
+            color_space,
+            ..self
         }
     }
-}
 
-impl Iterator for LightingColorsTransition {
-    type Item = LightingColors;
+    /// Applies easing and color space of transition options
+    pub fn with_options(self, options: &TransitionOptions) -> Self {
+        self.with_easing(options.easing)
+            .with_color_space(options.color_space)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.right.is_finished
-            && self.center.is_finished
-            && self.left.is_finished
-            && self.game.is_finished
-        {
-            return None;
+    /// Makes transition lasting `duration` start with given velocity
+    pub fn with_initial_velocity(self, velocity: LightingVelocity, duration: Duration) -> Self {
+        let seconds = duration.as_secs_f32();
+        let momentum = velocity
+            .zones()
+            .map(|[r, g, b]| [r * seconds, g * seconds, b * seconds]);
+        Self {
+            momentum: LightingVelocity::from_zones(momentum),
+            ..self
         }
+    }
+
+    /// Returns transition lasting `options.duration` from colors shown at `progress` of this
+    /// transition lasting `duration` to `to` colors.
+    ///
+    /// Current velocity is carried over, so the new transition continues smoothly. Velocity is
+    /// kept exactly by easings starting at rest (ease-in, ease-in-out), other easings add their
+    /// own initial slope to it.
+    pub fn retarget(
+        &self,
+        progress: f32,
+        duration: Duration,
+        to: LightingColors,
+        options: &TransitionOptions,
+    ) -> Self {
+        Self::new(self.sample(progress), to)
+            .with_options(options)
+            .with_initial_velocity(self.velocity(progress, duration), options.duration)
+    }
 
-        Some(LightingColors {
-            right: self.right.next(),
-            center: self.center.next(),
-            left: self.left.next(),
-            game: self.game.next(),
+    /// Returns unrounded sRGB channels of all zones at given progress
+    fn sample_rgb(&self, progress: f32) -> [Option<[f32; 3]>; 4] {
+        let progress = progress.clamp(0.0, 1.0);
+        let eased = self.easing.apply(progress);
+        let h10 = progress * (1.0 - progress) * (1.0 - progress);
+
+        let zones = [&self.right, &self.center, &self.left, &self.game];
+        let momentum = self.momentum.zones();
+        std::array::from_fn(|i| {
+            let [r, g, b] = zones[i].sample_rgb(self.color_space, eased)?;
+            let [mr, mg, mb] = momentum[i];
+            Some([r + mr * h10, g + mg * h10, b + mb * h10])
         })
     }
+
+    /// Returns colors at given progress in range `0.0..=1.0`. Progress is eased before
+    /// interpolating colors.
+    pub fn sample(&self, progress: f32) -> LightingColors {
+        let [right, center, left, game] = self
+            .sample_rgb(progress)
+            .map(|rgb| rgb.map(|rgb| self.color_space.quantize(rgb)));
+        LightingColors {
+            right,
+            center,
+            left,
+            game,
+        }
+    }
+
+    /// Returns velocity of transition lasting `duration` at given progress. The transition is
+    /// at rest once finished.
+    pub fn velocity(&self, progress: f32, duration: Duration) -> LightingVelocity {
+        const STEP: f32 = 1e-3;
+
+        if progress >= 1.0 || duration.is_zero() {
+            return LightingVelocity::default();
+        }
+
+        let start = (progress - STEP).max(0.0);
+        let end = (progress + STEP).min(1.0);
+        let seconds = (end - start) * duration.as_secs_f32();
+        let (before, after) = (self.sample_rgb(start), self.sample_rgb(end));
+
+        LightingVelocity::from_zones(std::array::from_fn(|i| match (before[i], after[i]) {
+            (Some(before), Some(after)) => {
+                std::array::from_fn(|c| (after[c] - before[c]) / seconds)
+            }
+            _ => [0.0; 3],
+        }))
+    }
+
+    /// Returns colors of transition lasting `duration` at the current time of the clock
+    pub fn sample_at(&self, clock: &dyn Clock, duration: Duration) -> LightingColors {
+        self.sample(progress(clock.elapsed(), duration))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn transition() -> LightingColorsTransition {
+        LightingColorsTransition::new(
+            LightingColors {
+                right: Some(Color::from(0x000000)),
+                center: Some(Color::from(0xFF8040)),
+                left: None,
+                game: Some(Color::from(0x102030)),
+            },
+            LightingColors {
+                right: Some(Color::from(0xFFFFFF)),
+                center: Some(Color::from(0x000000)),
+                left: Some(Color::from(0x0000FF)),
+                game: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_validate_options() {
+        assert!(
+            TransitionOptions::new(Duration::from_secs(1), 50)
+                .validate()
+                .is_ok()
+        );
+        assert!(matches!(
+            TransitionOptions::new(Duration::from_secs(1), 0).validate(),
+            Err(LomenError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_sample() {
+        let transition = transition();
+
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0x000000)),
+                center: Some(Color::from(0xFF8040)),
+                left: Some(Color::from(0x000000)),
+                game: Some(Color::from(0x102030)),
+            },
+            transition.sample(0.0)
+        );
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0x7F7F7F)),
+                center: Some(Color::from(0x7F4020)),
+                left: Some(Color::from(0x00007F)),
+                game: Some(Color::from(0x102030)),
+            },
+            transition.sample(0.5)
+        );
+        assert_eq!(
+            LightingColors {
+                right: Some(Color::from(0xFFFFFF)),
+                center: Some(Color::from(0x000000)),
+                left: Some(Color::from(0x0000FF)),
+                game: Some(Color::from(0x102030)),
+            },
+            transition.sample(1.0)
+        );
+        assert_eq!(transition.sample(1.0), transition.sample(7.0));
+        assert_eq!(transition.sample(0.0), transition.sample(-1.0));
+    }
+
+    #[test]
+    fn test_sample_unset_ends() {
+        let color = Some(Color::from(0x204060));
+
+        assert_eq!(color, ColorTransition::new(color, None).sample(0.0));
+        assert_eq!(color, ColorTransition::new(color, None).sample(1.0));
+        assert_eq!(
+            Some(Color::from(0x102030)),
+            ColorTransition::new(None, color).sample(0.5)
+        );
+        assert_eq!(color, ColorTransition::new(None, color).sample(1.0));
+        assert_eq!(None, ColorTransition::new(None, None).sample(0.5));
+    }
+
+    #[test]
+    fn test_sample_at() {
+        let transition = transition();
+        let clock = ManualClock::new();
+        let duration = Duration::from_millis(200);
+
+        assert_eq!(
+            transition.sample(0.0),
+            transition.sample_at(&clock, duration)
+        );
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(
+            transition.sample(0.5),
+            transition.sample_at(&clock, duration)
+        );
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(
+            transition.sample(1.0),
+            transition.sample_at(&clock, duration)
+        );
+    }
+
+    #[test]
+    fn test_sample_with_easing() {
+        let linear = transition();
+        let eased = transition().with_easing(Easing::Steps(2));
+
+        assert_eq!(linear.sample(0.0), eased.sample(0.4));
+        assert_eq!(linear.sample(0.5), eased.sample(0.6));
+        assert_eq!(linear.sample(1.0), eased.sample(1.0));
+        assert_ne!(
+            linear.sample(0.3),
+            transition().with_easing(Easing::EaseIn).sample(0.3)
+        );
+    }
+
+    #[test]
+    fn test_sample_in_color_space() {
+        let from = LightingColors {
+            right: Some(Color::from(0xFF0000)),
+            ..Default::default()
+        };
+        let to = LightingColors {
+            right: Some(Color::from(0x0000FF)),
+            ..Default::default()
+        };
+        let transition = LightingColorsTransition::new(from, to);
+
+        assert_eq!(Some(Color::from(0x7F007F)), transition.sample(0.5).right);
+        assert_eq!(
+            Some(Color::from(0xFF00FF)),
+            transition
+                .with_color_space(ColorSpace::Hsl)
+                .sample(0.5)
+                .right
+        );
+        assert_eq!(
+            None,
+            transition
+                .with_color_space(ColorSpace::Oklab)
+                .sample(0.5)
+                .left
+        );
+    }
+
+    #[test]
+    fn test_velocity() {
+        let transition = LightingColorsTransition::new(
+            LightingColors {
+                right: Some(Color::from(0x000000)),
+                ..Default::default()
+            },
+            LightingColors {
+                right: Some(Color::from(0xFF0000)),
+                ..Default::default()
+            },
+        );
+        let duration = Duration::from_millis(500);
+
+        let velocity = transition.velocity(0.5, duration);
+        assert!((velocity.right[0] - 510.0).abs() < 1.0);
+        assert_eq!([0.0; 3], velocity.center);
+        assert_eq!(
+            LightingVelocity::default(),
+            transition.velocity(1.0, duration)
+        );
+        assert!(
+            transition
+                .with_easing(Easing::EaseIn)
+                .velocity(0.0, duration)
+                .right[0]
+                .abs()
+                < 1.0
+        );
+    }
+
+    #[test]
+    fn test_retarget() {
+        let black = LightingColors {
+            right: Some(Color::from(0x000000)),
+            ..Default::default()
+        };
+        let white = LightingColors {
+            right: Some(Color::from(0xFFFFFF)),
+            ..Default::default()
+        };
+        let duration = Duration::from_secs(1);
+        let options = TransitionOptions::new(duration, 50).with_easing(Easing::EaseInOut);
+        let first = LightingColorsTransition::new(black, white).with_options(&options);
+
+        let second = first.retarget(0.3, duration, black, &options);
+
+        // Starts from the shown colors keeping velocity and still ends at the target
+        assert_eq!(first.sample(0.3), second.sample(0.0));
+        assert_eq!(black, second.sample(1.0));
+        let (before, after) = (
+            first.velocity(0.3, duration),
+            second.velocity(0.0, duration),
+        );
+        assert!(before.right[0] > 100.0);
+        assert!((before.right[0] - after.right[0]).abs() < 5.0);
+
+        // Momentum carries the color on before turning back
+        assert!(second.sample(0.05).right.unwrap().r > second.sample(0.0).right.unwrap().r);
+
+        // Transition at rest is retargeted as a fresh one
+        let finished = first.retarget(1.0, duration, black, &options);
+        assert_eq!(
+            LightingColorsTransition::new(white, black).with_options(&options),
+            finished
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(
+            0.25,
+            progress(Duration::from_millis(25), Duration::from_millis(100))
+        );
+        assert_eq!(
+            1.0,
+            progress(Duration::from_secs(5), Duration::from_secs(1))
+        );
+        assert_eq!(1.0, progress(Duration::ZERO, Duration::ZERO));
+    }
 }
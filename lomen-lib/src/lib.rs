@@ -1,5 +1,26 @@
+//! C API of lomen.
+//!
+//! Version 0.3.0 breaks the ABI of 0.2: exported functions use the `system` calling convention
+//! instead of `stdcall` and return `STATUS_*` codes, values are written through out-pointers.
+
+use lomen_core::animation::{AnimationHandle, Animator};
+use lomen_core::backend;
 use lomen_core::color::{Color, LightingColors};
-use lomen_core::control;
+use lomen_core::color_space::ColorSpace;
+use lomen_core::compositor::{
+    BlendMode, Compositor, Layer, LayerSlot, SharedCompositor, ZoneAlpha,
+};
+use lomen_core::device::{OmenDevice, SharedSessionCache};
+use lomen_core::easing::Easing;
+use lomen_core::effect::{Direction, Effect, EffectKind, EffectParams};
+use lomen_core::error::{LomenError, ReturnCode};
+use lomen_core::keyboard::KeyboardType;
+use lomen_core::scheduler::{FrameScheduler, FrameStats};
+use lomen_core::timeline::Timeline;
+use lomen_core::transition::TransitionOptions;
+use std::cell::RefCell;
+use std::ffi::{CStr, c_char};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
 use std::time::Duration;
 
 #[derive(Debug)]
@@ -11,41 +32,621 @@ pub struct ColorsArg {
     pub game: u64,
 }
 
+#[derive(Debug)]
+#[repr(C)]
+pub struct StatusArg {
+    pub enabled: bool,
+    pub brightness: u8,
+    pub level: u8,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct KeyboardArg {
+    pub keyboard_type: u8,
+    pub zone_count: u8,
+    pub numpad: bool,
+    pub per_key_rgb: bool,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CapabilitiesArg {
+    pub lighting_supported: bool,
+    pub light_bar_supported: bool,
+    pub brightness_supported: bool,
+    pub keyboard_type: u8,
+    pub zone_count: u8,
+    pub per_key_rgb: bool,
+}
+
+/// Transition parameters. `duration` is in milliseconds, `easing` is null-terminated easing name
+/// (e.g. `ease-in-out`, `cubic-bezier(0.4, 0, 0.2, 1)` or `steps(4)`) or null for linear one.
+/// `color_space` is null-terminated name of interpolation space (`srgb`, `linear-rgb`, `oklab`
+/// or `hsl`) or null for sRGB.
+#[derive(Debug)]
+#[repr(C)]
+pub struct TransitionArg {
+    pub duration: u64,
+    pub fps: u8,
+    pub easing: *const c_char,
+    pub color_space: *const c_char,
+}
+
+/// Effect parameters. `name` is null-terminated effect name (`breathing`, `cycle`, `rainbow`,
+/// `wave`, `strobe`, `candle` or `gradient`), `colors` points to `color_count` colors (may be
+/// null if there are none, unset colors are rejected), `speed` is number of cycles per second
+/// and `duration` is in milliseconds, zero means playing until cancelled.
+#[derive(Debug)]
+#[repr(C)]
+pub struct EffectArg {
+    pub name: *const c_char,
+    pub colors: *const u64,
+    pub color_count: usize,
+    pub speed: f32,
+    pub reverse: bool,
+    pub duration: u64,
+    pub fps: u8,
+}
+
+/// Layer options. `alpha` is opacity of right, center, left and game zone in range 0..=1,
+/// `blend` is null-terminated blend mode name (`normal`, `add`, `multiply` or `screen`) or null
+/// for normal one and `duration` is in milliseconds after which the layer is removed, zero keeps
+/// it until cleared.
+#[derive(Debug)]
+#[repr(C)]
+pub struct LayerArg {
+    pub alpha: [f32; 4],
+    pub blend: *const c_char,
+    pub duration: u64,
+}
+
+/// Statistics of the last transition, `elapsed` is in milliseconds
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FrameStatsArg {
+    pub frames: u32,
+    pub dropped: u32,
+    pub elapsed: u64,
+    pub fps: f32,
+}
+
 const NO_COLOR: u64 = 0xFFFFFFFF;
 
+/* Status codes returned by exported functions */
+pub const STATUS_OK: i32 = 0;
+pub const STATUS_CONNECTION_FAILED: i32 = 1;
+pub const STATUS_CLASS_NOT_FOUND: i32 = 2;
+pub const STATUS_RETURN_CODE: i32 = 3;
+pub const STATUS_INVALID_RESPONSE: i32 = 4;
+pub const STATUS_UNSUPPORTED: i32 = 5;
+pub const STATUS_INVALID_ARGUMENT: i32 = 6;
+pub const STATUS_BACKEND_ERROR: i32 = 7;
+pub const STATUS_IO_ERROR: i32 = 8;
+pub const STATUS_ACCESS_DENIED: i32 = 9;
+pub const STATUS_CANCELLED: i32 = 10;
+
+/* Compositor layers from the bottom one */
+pub const LAYER_BASE: u8 = 0;
+pub const LAYER_EFFECT: u8 = 1;
+pub const LAYER_NOTIFICATION: u8 = 2;
+
+const COMPOSITION_FPS: u8 = 50;
+
+/// Background animations shared by all threads
+static ANIMATOR: Mutex<Option<Animator>> = Mutex::new(None);
+/// Colors buffers cached by sessions of all threads and background animations
+static SESSION_CACHE: LazyLock<SharedSessionCache> = LazyLock::new(SharedSessionCache::default);
+/// Layers composited by `set_layer_*` functions
+static COMPOSITOR: LazyLock<SharedCompositor> =
+    LazyLock::new(|| Arc::new(Mutex::new(Compositor::new())));
+/// Background animation playing [`COMPOSITOR`]
+static COMPOSITION: Mutex<Option<AnimationHandle>> = Mutex::new(None);
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+    /// Session of the calling thread. Backend connection is bound to the thread, colors buffers
+    /// are shared with other threads through [`SESSION_CACHE`].
+    static DEVICE: RefCell<Option<OmenDevice>> = const { RefCell::new(None) };
+    static LAST_FRAME_STATS: RefCell<FrameStatsArg> = const {
+        RefCell::new(FrameStatsArg {
+            frames: 0,
+            dropped: 0,
+            elapsed: 0,
+            fps: 0.0,
+        })
+    };
+}
+
 #[unsafe(no_mangle)]
-pub extern "stdcall" fn is_lighting_supported() -> bool {
-    control::is_lighting_supported().unwrap()
+pub extern "system" fn is_lighting_supported(out_supported: *mut bool) -> i32 {
+    run(|device| {
+        let supported = device.is_lighting_supported()?;
+        write_out(out_supported, supported)
+    })
 }
 
 #[unsafe(no_mangle)]
-pub extern "stdcall" fn get_keyboard_type() -> u8 {
-    control::get_keyboard_type().unwrap()
+pub extern "system" fn get_keyboard_type(out_type: *mut u8) -> i32 {
+    run(|device| {
+        let keyboard_type = device.get_keyboard_type()?;
+        write_out(out_type, keyboard_type.code())
+    })
 }
 
 #[unsafe(no_mangle)]
-pub extern "stdcall" fn get_colors(out_data: *mut ColorsArg) {
-    if !out_data.is_null() {
-        let colors = control::get_colors().unwrap();
-        unsafe {
-            (*out_data).right = color_to_num(colors.right);
-            (*out_data).center = color_to_num(colors.center);
-            (*out_data).left = color_to_num(colors.left);
-            (*out_data).game = color_to_num(colors.game);
+pub extern "system" fn get_keyboard_info(out_info: *mut KeyboardArg) -> i32 {
+    run(|device| {
+        let keyboard_type = device.get_keyboard_type()?;
+        let capabilities = keyboard_type.capabilities();
+        write_out(
+            out_info,
+            KeyboardArg {
+                keyboard_type: keyboard_type.code(),
+                zone_count: capabilities.zone_count,
+                numpad: capabilities.numpad,
+                per_key_rgb: capabilities.per_key_rgb,
+            },
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn get_capabilities(out_capabilities: *mut CapabilitiesArg) -> i32 {
+    run(|device| {
+        let capabilities = device.get_capabilities()?;
+        write_out(
+            out_capabilities,
+            CapabilitiesArg {
+                lighting_supported: capabilities.lighting_supported,
+                light_bar_supported: capabilities.light_bar_supported,
+                brightness_supported: capabilities.brightness_supported,
+                keyboard_type: capabilities.keyboard_type.code(),
+                zone_count: capabilities.zone_count,
+                per_key_rgb: capabilities.per_key_rgb,
+            },
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn get_colors(out_data: *mut ColorsArg) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        let colors = device.get_colors()?;
+        write_out(out_data, colors_to_arg(&colors))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn set_colors(data: *const ColorsArg) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        stop_animation();
+        let colors = colors_to_write(device, data)?;
+        device.set_colors(&colors)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn transit_colors(data: *const ColorsArg, duration: u64, fps: u8) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        stop_animation();
+        let options = TransitionOptions::new(Duration::from_millis(duration), fps);
+        let colors = colors_to_write(device, data)?;
+        let stats = device.transit_colors(&colors, &options)?;
+        store_frame_stats(&stats);
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn transit_colors_with(
+    data: *const ColorsArg,
+    options: *const TransitionArg,
+) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        stop_animation();
+        let colors = colors_to_write(device, data)?;
+        let stats = device.transit_colors(&colors, &arg_to_options(options)?)?;
+        store_frame_stats(&stats);
+        Ok(())
+    })
+}
+
+/// Starts smooth change of keyboard lighting colors in background and returns immediately.
+/// Running animation is superseded, a running keyboard transition continues from the colors it
+/// has shown so that rapid successive calls (e.g. from a slider) stay smooth.
+#[unsafe(no_mangle)]
+pub extern "system" fn start_transit_colors(
+    data: *const ColorsArg,
+    options: *const TransitionArg,
+) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        let (colors, options) = (arg_to_colors(data)?, arg_to_options(options)?);
+        with_animator(|animator| animator.transit_colors(colors, options));
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn is_light_bar_supported(out_supported: *mut bool) -> i32 {
+    run(|device| {
+        let supported = device.is_light_bar_supported()?;
+        write_out(out_supported, supported)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn get_light_bar_colors(out_data: *mut ColorsArg) -> i32 {
+    run(|device| {
+        let colors = device.get_light_bar_colors()?;
+        write_out(out_data, colors_to_arg(&colors))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn set_light_bar_colors(data: *const ColorsArg) -> i32 {
+    run(|device| {
+        stop_animation();
+        let colors = colors_to_write(device, data)?;
+        device.set_light_bar_colors(&colors)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn transit_light_bar_colors(
+    data: *const ColorsArg,
+    duration: u64,
+    fps: u8,
+) -> i32 {
+    run(|device| {
+        stop_animation();
+        let options = TransitionOptions::new(Duration::from_millis(duration), fps);
+        let colors = colors_to_write(device, data)?;
+        let stats = device.transit_light_bar_colors(&colors, &options)?;
+        store_frame_stats(&stats);
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn transit_light_bar_colors_with(
+    data: *const ColorsArg,
+    options: *const TransitionArg,
+) -> i32 {
+    run(|device| {
+        stop_animation();
+        let colors = colors_to_write(device, data)?;
+        let stats = device.transit_light_bar_colors(&colors, &arg_to_options(options)?)?;
+        store_frame_stats(&stats);
+        Ok(())
+    })
+}
+
+/// Starts smooth change of light bar colors in background and returns immediately. Running
+/// animation is superseded.
+#[unsafe(no_mangle)]
+pub extern "system" fn start_transit_light_bar_colors(
+    data: *const ColorsArg,
+    options: *const TransitionArg,
+) -> i32 {
+    run_local(|| {
+        let (colors, options) = (arg_to_colors(data)?, arg_to_options(options)?);
+        with_animator(|animator| animator.transit_light_bar_colors(colors, options));
+        Ok(())
+    })
+}
+
+/// Starts playing effect on keyboard zones in background and returns immediately. Running
+/// animation is superseded.
+#[unsafe(no_mangle)]
+pub extern "system" fn start_effect(effect: *const EffectArg) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        let (effect, duration, fps) = arg_to_effect(effect)?;
+        // Checked before spawning so that invalid frame rate fails this call
+        FrameScheduler::endless(fps)?;
+        with_animator(|animator| animator.play_effect(effect, duration, fps));
+        Ok(())
+    })
+}
+
+/// Loads keyframe animation file (`.lomenanim` in TOML or JSON) and starts playing it in
+/// background. Invalid files are reported before anything is played.
+#[unsafe(no_mangle)]
+pub extern "system" fn start_animation_file(path: *const c_char) -> i32 {
+    run(|device| {
+        let path = arg_to_str(path)?
+            .ok_or_else(|| LomenError::InvalidArgument("path is null".to_string()))?;
+        let timeline = Timeline::load(path)?;
+        device.check_zone_lighting()?;
+        let (duration, fps) = (timeline.total_duration(), timeline.fps);
+        with_animator(|animator| animator.play_effect(Box::new(timeline), duration, fps));
+        Ok(())
+    })
+}
+
+/// Puts fixed colors into compositor layer (`LAYER_BASE`, `LAYER_EFFECT` or
+/// `LAYER_NOTIFICATION`) replacing the previous one. Layers are composited in background; unset
+/// zones are transparent. `options` may be null for opaque layer kept until cleared.
+///
+/// Direct color changes, effects and transitions stop the composition, the next layer change
+/// resumes it with the layers kept.
+#[unsafe(no_mangle)]
+pub extern "system" fn set_layer_colors(
+    layer: u8,
+    data: *const ColorsArg,
+    options: *const LayerArg,
+) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        let slot = arg_to_slot(layer)?;
+        let layer = arg_to_layer(Layer::colors(arg_to_colors(data)?), options)?;
+        set_layer(slot, layer);
+        Ok(())
+    })
+}
+
+/// Puts effect into compositor layer replacing the previous one. `duration` and `fps` of the
+/// effect are ignored, the layer lasts for `duration` of `options`.
+#[unsafe(no_mangle)]
+pub extern "system" fn set_layer_effect(
+    layer: u8,
+    effect: *const EffectArg,
+    options: *const LayerArg,
+) -> i32 {
+    run(|device| {
+        device.check_zone_lighting()?;
+        let slot = arg_to_slot(layer)?;
+        let (effect, _, _) = arg_to_effect(effect)?;
+        let layer = arg_to_layer(Layer::new(effect), options)?;
+        set_layer(slot, layer);
+        Ok(())
+    })
+}
+
+/// Removes compositor layer revealing the layers below
+#[unsafe(no_mangle)]
+pub extern "system" fn clear_layer(layer: u8) -> i32 {
+    run_local(|| {
+        let slot = arg_to_slot(layer)?;
+        lock_compositor().remove(slot);
+        Ok(())
+    })
+}
+
+/// Stops background animation and waits until it finishes
+#[unsafe(no_mangle)]
+pub extern "system" fn cancel_animation() -> i32 {
+    run_local(|| {
+        with_animator(Animator::cancel);
+        Ok(())
+    })
+}
+
+/// Waits until background animation finishes and stores its statistics. Fails with
+/// `STATUS_CANCELLED` if the animation was cancelled or superseded.
+#[unsafe(no_mangle)]
+pub extern "system" fn wait_animation() -> i32 {
+    run_local(|| {
+        if let Some(handle) = with_animator(|animator| animator.current().cloned()) {
+            store_frame_stats(&handle.wait()?);
         }
-    } else {
-        panic!("Out data pointer is null.");
-    }
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn is_animation_running(out_running: *mut bool) -> i32 {
+    run_local(|| {
+        let running = with_animator(|animator| animator.current().is_some_and(|h| h.is_running()));
+        write_out(out_running, running)
+    })
+}
+
+/// Writes progress of background animation in range 0..=1, 0 if none has been started
+#[unsafe(no_mangle)]
+pub extern "system" fn get_animation_progress(out_progress: *mut f32) -> i32 {
+    run_local(|| {
+        let progress = with_animator(|animator| animator.current().map_or(0.0, |h| h.progress()));
+        write_out(out_progress, progress)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn get_status(out_status: *mut StatusArg) -> i32 {
+    run(|device| {
+        let status = device.get_status()?;
+        write_out(
+            out_status,
+            StatusArg {
+                enabled: status.enabled,
+                brightness: status.brightness,
+                level: status.level,
+            },
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn get_brightness(out_brightness: *mut u8) -> i32 {
+    run(|device| {
+        let brightness = device.get_brightness()?;
+        write_out(out_brightness, brightness)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn set_brightness(brightness: u8) -> i32 {
+    run(|device| device.set_brightness(brightness))
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn set_lighting_enabled(enabled: bool) -> i32 {
+    run(|device| device.set_lighting_enabled(enabled))
+}
+
+/// Writes statistics of the last transition played in the calling thread
+#[unsafe(no_mangle)]
+pub extern "system" fn get_last_frame_stats(out_stats: *mut FrameStatsArg) -> i32 {
+    run_local(|| write_out(out_stats, LAST_FRAME_STATS.with(|last| *last.borrow())))
+}
+
+/// Drops colors cached by the library so that the next write reads them from the device again.
+/// Call it after colors have been changed by another process.
+#[unsafe(no_mangle)]
+pub extern "system" fn refresh_device() -> i32 {
+    run_local(|| {
+        SESSION_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .refresh();
+        Ok(())
+    })
 }
 
+/// Copies human-readable name of keyboard type code into the buffer as null-terminated string
+/// and returns full name length
 #[unsafe(no_mangle)]
-pub extern "stdcall" fn set_colors(data: *const ColorsArg) {
-    control::set_colors(&arg_to_colors(data)).unwrap()
+pub extern "system" fn get_keyboard_type_name(
+    keyboard_type: u8,
+    buffer: *mut u8,
+    size: usize,
+) -> usize {
+    write_str(&KeyboardType::from(keyboard_type).to_string(), buffer, size)
 }
 
+/// Copies message of the last error occurred in the calling thread into the buffer as
+/// null-terminated string and returns full message length
 #[unsafe(no_mangle)]
-pub extern "stdcall" fn transit_colors(data: *const ColorsArg, duration: u64, fps: u8) {
-    control::transit_colors(&arg_to_colors(data), Duration::from_millis(duration), fps).unwrap()
+pub extern "system" fn get_last_error(buffer: *mut u8, size: usize) -> usize {
+    LAST_ERROR.with(|last| write_str(&last.borrow(), buffer, size))
+}
+
+/// Runs `f` with device session of the calling thread opening it if needed. Session is closed
+/// on failure so that the next call reconnects.
+fn run(f: impl FnOnce(&mut OmenDevice) -> Result<(), LomenError>) -> i32 {
+    let result = DEVICE.with(|cell| {
+        let mut device = cell.borrow_mut();
+        if device.is_none() {
+            let backend = backend::default_backend()?;
+            *device = Some(OmenDevice::with_shared_cache(
+                backend,
+                SESSION_CACHE.clone(),
+            ));
+        }
+
+        let result = f(device.as_mut().unwrap());
+        if result.is_err() {
+            SESSION_CACHE
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .refresh();
+            *device = None;
+        }
+        result
+    });
+
+    run_local(|| result)
+}
+
+/// Runs `f` that does not need device session
+fn run_local(f: impl FnOnce() -> Result<(), LomenError>) -> i32 {
+    let (status, message) = match f() {
+        Ok(()) => (STATUS_OK, String::new()),
+        Err(e) => (error_status(&e), e.to_string()),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// Runs `f` with animator shared by all threads creating it if needed
+fn with_animator<T>(f: impl FnOnce(&mut Animator) -> T) -> T {
+    let mut animator = ANIMATOR.lock().unwrap_or_else(PoisonError::into_inner);
+    f(
+        animator
+            .get_or_insert_with(|| Animator::native().with_session_cache(SESSION_CACHE.clone())),
+    )
+}
+
+fn lock_compositor() -> MutexGuard<'static, Compositor> {
+    COMPOSITOR.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Puts layer into compositor and starts playing it unless it is already running
+fn set_layer(slot: LayerSlot, layer: Layer) {
+    lock_compositor().set(slot, layer);
+
+    let mut composition = COMPOSITION.lock().unwrap_or_else(PoisonError::into_inner);
+    if composition
+        .as_ref()
+        .is_none_or(|handle| !handle.is_running())
+    {
+        with_animator(|animator| {
+            let handle = animator.play_compositor(COMPOSITOR.clone(), COMPOSITION_FPS);
+            *composition = Some(handle.clone());
+            handle
+        });
+    }
+}
+
+/// Stops background animation before writing colors directly
+fn stop_animation() {
+    with_animator(Animator::cancel);
+}
+
+fn store_frame_stats(stats: &FrameStats) {
+    LAST_FRAME_STATS.with(|last| {
+        *last.borrow_mut() = FrameStatsArg {
+            frames: stats.frames,
+            dropped: stats.dropped,
+            elapsed: stats.elapsed.as_millis() as u64,
+            fps: stats.fps(),
+        }
+    });
+}
+
+fn error_status(error: &LomenError) -> i32 {
+    match error {
+        LomenError::Connection(_) => STATUS_CONNECTION_FAILED,
+        LomenError::ClassNotFound(_) => STATUS_CLASS_NOT_FOUND,
+        LomenError::ReturnCode(ReturnCode::AccessDenied) => STATUS_ACCESS_DENIED,
+        LomenError::ReturnCode(code) if code.is_not_supported() => STATUS_UNSUPPORTED,
+        LomenError::ReturnCode(_) => STATUS_RETURN_CODE,
+        LomenError::InvalidResponse(_) => STATUS_INVALID_RESPONSE,
+        LomenError::Unsupported(_) => STATUS_UNSUPPORTED,
+        LomenError::InvalidArgument(_) => STATUS_INVALID_ARGUMENT,
+        LomenError::Backend(_) => STATUS_BACKEND_ERROR,
+        LomenError::Io(_) => STATUS_IO_ERROR,
+        LomenError::Cancelled => STATUS_CANCELLED,
+    }
+}
+
+fn write_out<T>(ptr: *mut T, value: T) -> Result<(), LomenError> {
+    if ptr.is_null() {
+        return Err(LomenError::InvalidArgument(
+            "output pointer is null".to_string(),
+        ));
+    }
+
+    unsafe { ptr.write(value) };
+    Ok(())
+}
+
+/// Writes possibly truncated null-terminated string and returns its full length
+fn write_str(s: &str, buffer: *mut u8, size: usize) -> usize {
+    if !buffer.is_null() && size > 0 {
+        let len = s.len().min(size - 1);
+        unsafe {
+            buffer.copy_from_nonoverlapping(s.as_ptr(), len);
+            buffer.add(len).write(0);
+        }
+    }
+    s.len()
 }
 
 fn num_to_color(color: u64) -> Option<Color> {
@@ -56,19 +657,168 @@ fn num_to_color(color: u64) -> Option<Color> {
     }
 }
 
-fn arg_to_colors(data: *const ColorsArg) -> LightingColors {
+/// Reads colors written directly. Cached buffers are refreshed before partial writes, so that
+/// unset zones keep colors changed by other processes instead of stale cached ones.
+fn colors_to_write(
+    device: &mut OmenDevice,
+    data: *const ColorsArg,
+) -> Result<LightingColors, LomenError> {
+    let colors = arg_to_colors(data)?;
+    if [colors.right, colors.center, colors.left, colors.game].contains(&None) {
+        device.refresh();
+    }
+    Ok(colors)
+}
+
+fn arg_to_colors(data: *const ColorsArg) -> Result<LightingColors, LomenError> {
     if !data.is_null() {
         unsafe {
-            LightingColors {
+            Ok(LightingColors {
                 right: num_to_color((*data).right),
                 center: num_to_color((*data).center),
                 left: num_to_color((*data).left),
                 game: num_to_color((*data).game),
-            }
+            })
         }
     } else {
-        panic!("Data pointer is null.");
+        Err(LomenError::InvalidArgument(
+            "data pointer is null".to_string(),
+        ))
+    }
+}
+
+fn colors_to_arg(colors: &LightingColors) -> ColorsArg {
+    ColorsArg {
+        right: color_to_num(colors.right),
+        center: color_to_num(colors.center),
+        left: color_to_num(colors.left),
+        game: color_to_num(colors.game),
+    }
+}
+
+/// Reads optional null-terminated UTF-8 string
+fn arg_to_str<'a>(s: *const c_char) -> Result<Option<&'a str>, LomenError> {
+    if s.is_null() {
+        return Ok(None);
     }
+
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(Some)
+        .map_err(|_| LomenError::InvalidArgument("string is not valid UTF-8".to_string()))
+}
+
+fn arg_to_options(options: *const TransitionArg) -> Result<TransitionOptions, LomenError> {
+    if options.is_null() {
+        return Err(LomenError::InvalidArgument(
+            "options pointer is null".to_string(),
+        ));
+    }
+
+    let options = unsafe { &*options };
+    let easing = match arg_to_str(options.easing)? {
+        Some(easing) => easing.parse()?,
+        None => Easing::Linear,
+    };
+    let color_space = match arg_to_str(options.color_space)? {
+        Some(color_space) => color_space.parse()?,
+        None => ColorSpace::Srgb,
+    };
+
+    let options = TransitionOptions::new(Duration::from_millis(options.duration), options.fps)
+        .with_easing(easing)
+        .with_color_space(color_space);
+    options.validate()?;
+    Ok(options)
+}
+
+fn arg_to_slot(layer: u8) -> Result<LayerSlot, LomenError> {
+    match layer {
+        LAYER_BASE => Ok(LayerSlot::Base),
+        LAYER_EFFECT => Ok(LayerSlot::Effect),
+        LAYER_NOTIFICATION => Ok(LayerSlot::Notification),
+        _ => Err(LomenError::InvalidArgument(format!(
+            "unknown layer {}",
+            layer
+        ))),
+    }
+}
+
+fn arg_to_layer(layer: Layer, options: *const LayerArg) -> Result<Layer, LomenError> {
+    if options.is_null() {
+        return Ok(layer);
+    }
+
+    let options = unsafe { &*options };
+    if !options
+        .alpha
+        .iter()
+        .all(|alpha| (0.0..=1.0).contains(alpha))
+    {
+        return Err(LomenError::InvalidArgument(
+            "alpha must be in range 0..=1".to_string(),
+        ));
+    }
+    let [right, center, left, game] = options.alpha;
+    let blend = match arg_to_str(options.blend)? {
+        Some(blend) => blend.parse()?,
+        None => BlendMode::Normal,
+    };
+
+    let layer = layer
+        .with_zone_alpha(ZoneAlpha {
+            right,
+            center,
+            left,
+            game,
+        })
+        .with_blend(blend);
+    Ok(match options.duration {
+        0 => layer,
+        duration => layer.with_duration(Duration::from_millis(duration)),
+    })
+}
+
+type EffectSpec = (Box<dyn Effect>, Option<Duration>, u8);
+
+fn arg_to_effect(effect: *const EffectArg) -> Result<EffectSpec, LomenError> {
+    if effect.is_null() {
+        return Err(LomenError::InvalidArgument(
+            "effect pointer is null".to_string(),
+        ));
+    }
+
+    let effect = unsafe { &*effect };
+    let kind: EffectKind = arg_to_str(effect.name)?
+        .ok_or_else(|| LomenError::InvalidArgument("effect name is null".to_string()))?
+        .parse()?;
+    let colors = if effect.colors.is_null() || effect.color_count == 0 {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(effect.colors, effect.color_count) }
+            .iter()
+            .map(|&color| {
+                num_to_color(color).ok_or_else(|| {
+                    LomenError::InvalidArgument("effect colors must be set".to_string())
+                })
+            })
+            .collect::<Result<_, _>>()?
+    };
+    let params = EffectParams {
+        colors,
+        speed: effect.speed,
+        direction: if effect.reverse {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        },
+    };
+    let duration = match effect.duration {
+        0 => None,
+        duration => Some(Duration::from_millis(duration)),
+    };
+
+    Ok((kind.create(&params)?, duration, effect.fps))
 }
 
 fn color_to_num(color: Option<Color>) -> u64 {